@@ -8,39 +8,161 @@ use std::error::Error;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::usize;
 
+use band_limited::BandLimitedTable;
+use envelope::Envelope;
+use modulation::ModState;
+use scope::ScopeBuffer;
+use voice::{PlayHead, VoicePool};
+
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
 };
 
 mod audio_utils;
+mod band_limited;
+mod envelope;
+mod fft;
+mod harmonics;
+mod interpolation;
+mod lfo;
+mod modulation;
+mod modulator;
+mod segment_curve;
+mod node_fit;
+mod pitch;
+mod scope;
+mod spectrum;
+mod voice;
+mod wav;
+mod wavetable;
+
+pub use envelope::{AdsrSettings, EnvelopeCurve};
+pub use harmonics::{Harmonics, NUM_HARMONICS};
+pub use interpolation::InterpolationMode;
+pub use lfo::{LfoSettings, LfoShape, MIN_LFO_RATE, MAX_LFO_RATE};
+pub use modulation::{ModMatrix, ModRoute, ModSource, ModTarget, MAX_ROUTES, NUM_LFOS};
+pub use modulator::{Layer, ModulatorMode, ModulatorSettings, MIN_MODULATOR_SECONDS, MAX_MODULATOR_SECONDS};
+pub use pitch::Pitch;
+pub use segment_curve::SegmentCurve;
+pub use spectrum::SpectrumBin;
+pub use voice::{StealPolicy, MAX_VOICES};
+pub use wav::{BitDepth, WavSpec};
+pub use wavetable::{SMALL_TABLE_LEN, LARGE_TABLE_LEN};
+
+// parameters the output device is opened with
+const OUTPUT_SAMPLE_RATE:usize = 44100;
+const OUTPUT_BLOCK_LEN:usize = 4410;
+// long enough that the scope always shows at least one seam between two output blocks
+const SCOPE_LEN:usize = OUTPUT_BLOCK_LEN * 2;
+// how many times a second the modulation matrix is read, whatever the sample rate
+const CONTROL_RATE:usize = 100;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct WaveNode {
     wave_pos:f32,
     amplitude:f32,
+    // shape of the segment between this node and its right neighbor
+    curve:SegmentCurve,
 }
 
+impl WaveNode {
+    pub fn new(wave_pos:f32, amplitude:f32, curve:SegmentCurve) -> Self {
+        WaveNode { wave_pos, amplitude, curve }
+    }
+
+    pub fn wave_pos(&self) -> f32 {
+        self.wave_pos
+    }
+
+    pub fn amplitude(&self) -> f32 {
+        self.amplitude
+    }
+
+    pub fn curve(&self) -> SegmentCurve {
+        self.curve
+    }
+}
+
+// pitch a new wave starts playing at, A2
+const DEFAULT_FREQUENCY:f32 = 110.0;
+const MAX_FREQUENCY:f32 = 20000.0;
+// speed of the vibrato the mod wheel brings in
+const VIBRATO_RATE:f64 = 5.5;
+
+#[derive(Clone)]
 struct Wave {
     node_list:Vec<WaveNode>,
-    curr_node_index:usize,
-    curr_node:WaveNode,
-    interval_progress:f32,
-    freq_mult:f32,
+    // play head of the wave playing on its own, without a keyboard
+    head:PlayHead,
+    // in Hz, what the wave plays at on its own, played back against the sample rate of the output
+    frequency:f32,
+    interpolation:InterpolationMode,
+    // play back from band limited copies of the cycle instead of evaluating the nodes directly
+    band_limited:bool,
+    // built by whoever changed the shape, on their own thread, and swapped in once it's ready. Thrown away whenever
+    // the shape changes, the nodes are played directly until the new one is in
+    band_limited_table:Option<Arc<BandLimitedTable>>,
+    // counts every change to the shape, so a table built from an older shape isn't swapped in
+    shape_version:u64,
+    // for the spectrum panel, worked out the first time it's drawn after the shape changes
+    harmonic_levels:Option<Arc<[f32]>>,
+    // how far the vibrato swings the pitch either way, 0 for none
+    vibrato_depth_cents:f32,
 }
 
 impl Wave {
     // Create an empty linked list
     fn new(init_node:WaveNode) -> Self {
-        Wave { node_list:vec![init_node], curr_node_index:0, curr_node:init_node.clone(), interval_progress:0.0f32, freq_mult:2.0 }
+        Wave {
+            node_list:vec![init_node],
+            head:PlayHead::default(),
+            frequency:DEFAULT_FREQUENCY,
+            interpolation:InterpolationMode::Linear,
+            band_limited:true,
+            band_limited_table:None,
+            shape_version:0,
+            harmonic_levels:None,
+            vibrato_depth_cents:0.0,
+        }
+    }
+
+    // anything that changes what one cycle looks like has to call this so the band limited table gets rebuilt
+    fn shape_changed(&mut self) {
+        self.band_limited_table = None;
+        self.harmonic_levels = None;
+        self.shape_version = self.shape_version.wrapping_add(1);
+    }
+
+    pub fn set_interpolation(&mut self, mode:InterpolationMode) {
+        self.interpolation = mode;
+        self.shape_changed();
+    }
+
+    // applies a change to the curve of the segment starting at node_index, returns the new curve
+    fn update_node_curve(&mut self, node_index:usize, update:impl FnOnce(SegmentCurve) -> SegmentCurve) -> Option<SegmentCurve> {
+        let node = self.node_list.get_mut(node_index)?;
+        node.curve = update(node.curve);
+        let curve = node.curve;
+        self.shape_changed();
+        Some(curve)
     }
 
     pub fn set_freq(&mut self, new_freq:f32){
-        self.freq_mult = new_freq.clamp(0.0, 100.0)
+        self.frequency = new_freq.clamp(0.0, MAX_FREQUENCY)
+    }
+
+    // swaps out every node at once, the play head is a position in the cycle so it carries on from where it was
+    fn set_nodes(&mut self, mut nodes:Vec<WaveNode>) {
+        nodes.sort_by(|a, b| a.wave_pos.total_cmp(&b.wave_pos));
+        nodes.dedup_by(|a, b| a.wave_pos == b.wave_pos);
+        self.node_list = nodes;
+        self.shape_changed();
     }
 
     // Add a node to the wave
     fn insert_node(&mut self, new_node:WaveNode) { 
+        self.shape_changed();
         // if empty list, populate the head, else search for place within list where this fits
         match self.node_list.is_empty() {
             true => self.node_list.push(new_node),
@@ -62,72 +184,132 @@ impl Wave {
         }
     }
 
-    fn peek_next_node(&self) -> &WaveNode{
-        &self.node_list[(self.curr_node_index + 1) % self.node_list.len()]
+    fn remove_node(&mut self, node_index:usize) -> Option<WaveNode> {
+        if node_index >= self.node_list.len() {
+            return None
+        }
+        self.shape_changed();
+        Some(self.node_list.remove(node_index))
     }
 
-    fn incr_curr_node(&mut self) {
-        self.curr_node_index = (self.curr_node_index + 1) % self.node_list.len();
-        self.curr_node = self.node_list[self.curr_node_index];
+    // moves the node at node_index to a new position and amplitude, keeping its curve and the sort by wave pos
+    // returns the index the node ended up at, or None if there is no such node or another one already sits at wave_pos
+    fn move_node(&mut self, node_index:usize, wave_pos:f32, amplitude:f32) -> Option<usize> {
+        if node_index >= self.node_list.len() {
+            return None
+        }
+        if self.node_list.iter().enumerate().any(|(index, node)| index != node_index && node.wave_pos == wave_pos) {
+            return None
+        }
+
+        let mut node = self.node_list.remove(node_index);
+        node.wave_pos = wave_pos;
+        node.amplitude = amplitude;
+        let new_index = self.node_list.partition_point(|probe| probe.wave_pos < wave_pos);
+        self.node_list.insert(new_index, node);
+        self.shape_changed();
+        Some(new_index)
     }
 
-    fn interval_len_in_samples(&self, start_node:&WaveNode, end_node:&WaveNode, bufsize:usize) -> f32{
-        let sample_len_of_wave = (bufsize as f32 / self.freq_mult);
+    // value of the segment that starts at the node at start_index, progress is how far through the segment we are (0.0 to 1.0)
+    fn segment_value(&self, start_index:usize, progress:f32) -> f32 {
+        let len = self.node_list.len() as isize;
+        // gather the node before the segment, the two nodes that bound it, and the node after it, wrapping around the list
+        // positions are shifted by a whole wave for every wrap so they are always increasing across the window
+        let mut points = [(0.0f32, 0.0f32); 4];
+        for (point, rel_index) in points.iter_mut().zip(-1isize..3) {
+            let index = start_index as isize + rel_index;
+            let node = &self.node_list[index.rem_euclid(len) as usize];
+            *point = (node.wave_pos + index.div_euclid(len) as f32, node.amplitude);
+        }
+        let curve = self.node_list[start_index].curve;
+        self.interpolation.interpolate(points, curve.shape(progress))
+    }
+
+    // value of the wave at a position within its cycle (0.0 to 1.0), doesn't use or move the play head
+    fn value_at(&self, wave_pos:f32) -> f32 {
+        let len = self.node_list.len();
+        // a lone node is a flat line at its amplitude, the same as it's drawn
+        if len == 0 {
+            return 0.0
+        }
 
-        // get length of interval relative to the entire wave ( will be a fraction )
-        let interval_rel_len = match end_node.wave_pos <= start_node.wave_pos {
-            true => (end_node.wave_pos + 1.0) - start_node.wave_pos,
-            false => end_node.wave_pos - start_node.wave_pos
+        let pos = wave_pos.rem_euclid(1.0);
+        // the segment starts at the last node at or before pos, anything before the first node is in the segment that wraps around
+        let start_index = match self.node_list.partition_point(|node| node.wave_pos <= pos) {
+            0 => len - 1,
+            after => after - 1,
         };
+        let start_pos = self.node_list[start_index].wave_pos;
+        let end_pos = self.node_list[(start_index + 1) % len].wave_pos;
+        let seg_len = match end_pos <= start_pos {
+            true => (end_pos + 1.0) - start_pos,
+            false => end_pos - start_pos,
+        };
+        self.segment_value(start_index, (pos - start_pos).rem_euclid(1.0) / seg_len)
+    }
 
-        // apply the freq multiplier to determine the number of samples in this interval
-        (interval_rel_len * sample_len_of_wave) // recip, because increasing the freq should shorten the wave
+    // one cycle of the wave sampled at table_len evenly spaced points
+    fn resample(&self, table_len:usize) -> Vec<f32> {
+        (0..table_len).map(|index| self.value_at(index as f32 / table_len as f32)).collect()
     }
 
-    fn interval_samples_remaining(intvl_sample_len:f32, curr_progress:f32) -> usize{
-        ((1.0f32 - curr_progress) * intvl_sample_len) as usize
+    // takes on how another wave plays, but keeps its own nodes
+    fn play_like(&mut self, wave:&Wave) {
+        self.frequency = wave.frequency;
+        self.interpolation = wave.interpolation;
+        self.vibrato_depth_cents = wave.vibrato_depth_cents;
     }
 
-    fn piecewise_linear(&mut self, buf: &mut [(f32, f32)]) -> f32 {
+    // whether playback is waiting on a band limited table for the shape as it is now
+    fn needs_band_limited_table(&self) -> bool {
+        self.band_limited && self.band_limited_table.is_none() && !self.node_list.is_empty()
+    }
 
-        if self.node_list.len() < 2 {
+    // adds the wave into the buffer, scaled by a gain asked for once per sample, stepping the play head through the
+    // cycle at frequency. Returns the loudest sample it added
+    fn play(&self, head:&mut PlayHead, frequency:f32, mut gain:impl FnMut() -> f32, buf: &mut [(f32, f32)], sample_rate:usize) -> f32 {
+
+        if self.node_list.is_empty() {
             return 0.0
         }
 
-        let mut curr_sample: usize = 0;
-
-        while curr_sample < buf.len() {
-            // calculate the end index of this interval based on the play head and progress
-            let interval_len_samples = Self::interval_len_in_samples(&self, &self.curr_node, self.peek_next_node(), buf.len());
-            let intvl_samples_remain = Self::interval_samples_remaining(interval_len_samples, self.interval_progress);
-            let end_sample = curr_sample + intvl_samples_remain;
-            let progress_incr = 1.0 / interval_len_samples;
-
-            //log::warn!("interval len: {interval_len_samples}\nrem_sample: {end_sample}\ncurr_sample: {curr_sample} \nprogress:{:?}", self.interval_progress);
-            // do a while loop between the start and end points of this interval
-            while curr_sample < buf.len() && curr_sample < end_sample {
-                // calculate value of this index into the buffer
-                let value = self.curr_node.amplitude * (1.0 - self.interval_progress) + self.peek_next_node().amplitude * self.interval_progress;
-                buf[curr_sample].0 = value;
-                buf[curr_sample].1 = value;
-
-                // if curr_sample == end_sample - 1 {
-                //     log::warn!("value at end of interval is: {value}\n progress is {:?}", self.interval_progress);
-                // }
-
-                self.interval_progress += progress_incr;
-                curr_sample += 1;
-            }
+        let phase_incr = frequency as f64 / sample_rate as f64;
+        let vibrato_incr = VIBRATO_RATE / sample_rate as f64;
+        let mut loudness = 0.0f32;
+        match self.band_limited_table.as_ref().filter(|_| self.band_limited) {
+            Some(table) => {
+                // the frequency is the same for the whole buffer (give or take the vibrato), so the level only has to
+                // be picked once
+                let table_level = table.level_for(frequency, sample_rate as f32);
+                for frame in buf.iter_mut() {
+                    let value = BandLimitedTable::read(table_level, head.phase as f32) * gain();
+                    *frame = (frame.0 + value, frame.1 + value);
+                    loudness = loudness.max(value.abs());
+                    self.advance_play_head(head, phase_incr, vibrato_incr);
+                }
+            },
+            None => {
+                for frame in buf.iter_mut() {
+                    let value = self.value_at(head.phase as f32) * gain();
+                    *frame = (frame.0 + value, frame.1 + value);
+                    loudness = loudness.max(value.abs());
+                    self.advance_play_head(head, phase_incr, vibrato_incr);
+                }
+            },
+        }
+        loudness
+    }
 
-            if curr_sample < buf.len() {
-                self.incr_curr_node();
-                self.interval_progress = 0.0;
-            }
+    // moves a play head on by a sample, bent by wherever the vibrato is in its cycle
+    fn advance_play_head(&self, head:&mut PlayHead, phase_incr:f64, vibrato_incr:f64) {
+        let mut incr = phase_incr;
+        if self.vibrato_depth_cents != 0.0 {
+            let cents = self.vibrato_depth_cents as f64 * (2.0 * std::f64::consts::PI * head.vibrato_phase).sin();
+            incr *= (cents / 1200.0).exp2();
+            head.vibrato_phase = (head.vibrato_phase + vibrato_incr).fract();
         }
-        
-        // return the progress point of the next sample that fall outside this frame
-        // it will be used as the offset for generating the next frame
-        self.interval_progress // TODO: remove, this is just to shut the linter up
+        head.phase = (head.phase + incr).fract();
     }
 }
 
@@ -139,9 +321,34 @@ impl std::fmt::Display for Wave {
 
 
 
-struct AudioState {
+pub struct AudioState {
     audio_device:Option<Box<dyn tinyaudio::BaseAudioOutputDevice>>,
     wave:Option<Wave>,
+    // snapshots of the wave stacked up to be exported together as a multi frame wavetable
+    wavetable_frames:Vec<Wave>,
+    // the latest output, for the scope drawn over the wave view
+    scope:ScopeBuffer,
+    // notes being played from a keyboard
+    voices:VoicePool,
+    // the wave plays on its own at its own frequency while this gate is open, it opens when the engine starts and
+    // closes once a keyboard is played
+    drone_envelope:Envelope,
+    // envelope shape for the wave on its own and every voice
+    adsr:AdsrSettings,
+    // applied on top of every voice
+    pitch_bend_cents:f32,
+    // drawn on its own layer of the editor and read through slowly as a modulation source
+    modulator:Option<Wave>,
+    modulator_settings:ModulatorSettings,
+    // routes from the LFOs, envelope and modulator to the pitch, level and nodes
+    mod_matrix:ModMatrix,
+    // where the wave playing on its own is in each modulation source
+    drone_modulation:ModState,
+    // which shape the node editing calls change
+    edit_layer:Layer,
+    // the wave with the node routes applied, rewritten in place every block so the audio thread doesn't allocate
+    // once it has been made
+    modulated:Option<Wave>,
 }
 
 impl AudioState{
@@ -149,29 +356,154 @@ impl AudioState{
         AudioState{ 
             audio_device: None,
             wave: None,
+            wavetable_frames: vec![],
+            scope: ScopeBuffer::new(SCOPE_LEN),
+            voices: VoicePool::new(voice::DEFAULT_VOICES, StealPolicy::Oldest),
+            drone_envelope: {
+                let mut envelope = Envelope::new();
+                envelope.gate_on();
+                envelope
+            },
+            adsr: AdsrSettings::default(),
+            pitch_bend_cents: 0.0,
+            modulator: None,
+            modulator_settings: ModulatorSettings::default(),
+            mod_matrix: ModMatrix::default(),
+            drone_modulation: ModState::default(),
+            edit_layer: Layer::Wave,
+            modulated: None,
+        }
+    }
+
+    fn layer(&self, layer:Layer) -> &Option<Wave> {
+        match layer {
+            Layer::Wave => &self.wave,
+            Layer::Modulator => &self.modulator,
+        }
+    }
+
+    fn layer_mut(&mut self, layer:Layer) -> &mut Option<Wave> {
+        match layer {
+            Layer::Wave => &mut self.wave,
+            Layer::Modulator => &mut self.modulator,
         }
     }
 
-    pub fn set_new_freq_from_delta(&mut self, delta:f32) {
-        // TODO: learn how the "cents" pitch measurement system works, just increaing the multiplier linearly makes it so the pitch goes up
-        // a lot more with each step than it does in the lower registers. I want a smooth pitch transition
-        let curr_freq = self.wave.as_mut().unwrap().freq_mult;
-        self.wave.as_mut().unwrap().set_freq(curr_freq + delta);
+    // a shape with something drawn on it, a shape whose nodes have all been removed keeps its settings but is treated
+    // as not being there
+    fn drawn(&self, layer:Layer) -> Option<&Wave> {
+        self.layer(layer).as_ref().filter(|wave| !wave.node_list.is_empty())
+    }
+
+    // the shape the editor is working on
+    fn edited(&mut self) -> &mut Option<Wave> {
+        self.layer_mut(self.edit_layer)
+    }
+
+    // moves the pitch by some number of cents, stepping in cents rather than Hz means every step sounds
+    // like the same sized change no matter which register the wave is playing in
+    pub fn set_new_pitch_from_delta(&mut self, cents:f32) -> Option<Pitch> {
+        let wave = self.wave.as_mut()?;
+        let pitch = Pitch::from_frequency(wave.frequency).transposed(cents);
+        wave.set_freq(pitch.frequency());
+        Some(pitch)
+    }
+
+    // pitch of the newest voice with the pitch wheel applied, or of the wave on its own if there are no voices
+    fn playing_pitch(&self) -> Option<Pitch> {
+        let wave = self.drawn(Layer::Wave)?;
+        match self.voices.newest() {
+            Some(voice) => Some(Pitch::from_frequency(voice.frequency).transposed(self.pitch_bend_cents)),
+            None => Some(Pitch::from_frequency(wave.frequency)),
+        }
     }
 
     pub fn render(&mut self, buf: &mut [(f32, f32)], params: tinyaudio::OutputDeviceParameters) {
         buf.fill((0.0, 0.0));
         
-        // TODO: what is the system by which the user can switch between rendering techniques?
+        // the technique used between nodes is set per wave, see Wave::set_interpolation
 
         // Fill audio buffer based on nodes in the Shaper Nodes vector
-        // functions in the AudioBufGen module also return the progess point of the sample in the buffer
-        // generated immediately after this one, this can be used as the offset for the next buffer
-        // Self::piecewise_linear(buf, &mut self.play_state.as_mut().unwrap(), &self.freq_mult);
+        // the wave keeps its play head between calls, so it picks up exactly where the last buffer left off
+        // regardless of how big the device makes each buffer
 
         // TODO: I don't really like that the rendering methods are a part of the "wave" structure
 
-        self.wave.as_mut().unwrap().piecewise_linear(buf);
+        // the modulation is read once per control block, so the buffer is played a block at a time
+        let block_len = (params.sample_rate / CONTROL_RATE).max(1);
+        for block in buf.chunks_mut(block_len) {
+            self.render_block(block, params.sample_rate);
+        }
+        self.voices.remove_finished();
+        // silence goes in too, so the trace flattens out when the wave is removed
+        self.scope.push_frames(buf);
+    }
+
+    // plays one control block, every source is read at its start
+    fn render_block(&mut self, buf: &mut [(f32, f32)], sample_rate:usize) {
+        // the device can start asking for samples before the first node has been placed
+        let Some(wave) = self.wave.as_mut() else {
+            return
+        };
+        let adsr = self.adsr;
+        let matrix = self.mod_matrix;
+        // what drawn() gives, without borrowing the rest of self while the wave is borrowed
+        let modulator = self.modulator.as_ref().filter(|shape| !shape.node_list.is_empty());
+        let modulator_settings = self.modulator_settings;
+        let sample_rate_hz = sample_rate as f32;
+
+        // the nodes are shared by every voice, so routes to them follow the newest held note, or the wave on its own
+        let lead_values = match self.voices.newest() {
+            Some(voice) => matrix.sources(&voice.modulation, voice.envelope.level(), modulator),
+            None => matrix.sources(&self.drone_modulation, self.drone_envelope.level(), modulator),
+        };
+        // the modulated shape changes every block, far too often to build a band limited table for, so it's played
+        // by evaluating its nodes directly
+        let modulated = self.modulated.get_or_insert_with(|| {
+            Wave { band_limited:false, band_limited_table:None, harmonic_levels:None, ..wave.clone() }
+        });
+        let playing:&Wave = match matrix.modulate_nodes(&wave.node_list, &lead_values, &mut modulated.node_list) {
+            true => {
+                modulated.play_like(wave);
+                modulated
+            },
+            false => wave,
+        };
+        let mut drone_head = wave.head;
+
+        // the play head waits where it is once the release has finished
+        if !self.drone_envelope.is_done() {
+            let values = matrix.sources(&self.drone_modulation, self.drone_envelope.level(), modulator);
+            let frequency = playing.frequency * (matrix.pitch_cents(&values) / 1200.0).exp2();
+            let target_gain = matrix.gain(&values);
+            let mut mod_gain = gain_ramp(self.drone_modulation.ramp_gain(target_gain), target_gain, buf.len());
+            let envelope = &mut self.drone_envelope;
+            playing.play(&mut drone_head, frequency, || envelope.next(&adsr, sample_rate_hz) * mod_gain(), buf, sample_rate);
+            matrix.advance(&mut self.drone_modulation, &modulator_settings, buf.len(), sample_rate_hz);
+        }
+        // every voice is mixed in at its own pitch, the pitch wheel bends them all together
+        for voice in self.voices.voices_mut() {
+            let values = matrix.sources(&voice.modulation, voice.envelope.level(), modulator);
+            let frequency = voice.frequency * ((self.pitch_bend_cents + matrix.pitch_cents(&values)) / 1200.0).exp2();
+            let target_gain = matrix.gain(&values);
+            let mut mod_gain = gain_ramp(voice.modulation.ramp_gain(target_gain), target_gain, buf.len());
+            let voice::Voice { head, envelope, level, .. } = voice;
+            let gain = || envelope.next(&adsr, sample_rate_hz) * *level * mod_gain();
+            voice.loudness = playing.play(head, frequency, gain, buf, sample_rate);
+            matrix.advance(&mut voice.modulation, &modulator_settings, buf.len(), sample_rate_hz);
+        }
+        // the modulated copy only lasts the block, the play head lives on the wave
+        wave.head = drone_head;
+    }
+}
+
+// steps evenly from one level to another over a block, asked for once per sample
+fn gain_ramp(from:f32, to:f32, len:usize) -> impl FnMut() -> f32 {
+    let step = (to - from) / len.max(1) as f32;
+    let mut gain = from;
+    move || {
+        gain += step;
+        gain
     }
 }
 
@@ -203,8 +535,8 @@ impl SoundEngine {
 
         let params: tinyaudio::OutputDeviceParameters = tinyaudio::OutputDeviceParameters {
             channels_count: 2,
-            sample_rate: 44100,
-            channel_sample_count: 4410,
+            sample_rate: OUTPUT_SAMPLE_RATE,
+            channel_sample_count: OUTPUT_BLOCK_LEN,
         };
 
         // TODO: figure out rendering
@@ -241,28 +573,417 @@ impl SoundEngine {
         self.0.lock().unwrap()
     }
 
+    // makes a change to one of the shapes, then gets the wave ready to play as it now is
+    fn edit_shape<R>(&self, edit:impl FnOnce(&mut AudioState) -> R) -> R {
+        let result = edit(&mut self.state());
+        self.build_band_limited_table();
+        result
+    }
+
+    // builds the band limited table for the wave as it is now, called after every edit to a shape so the audio thread
+    // only ever has to pick up a finished table. The lock is let go while the FFTs run, so the audio thread isn't
+    // held up by them
+    fn build_band_limited_table(&self) {
+        let (cycle, shape_version) = {
+            let state = self.state();
+            let Some(wave) = state.wave.as_ref().filter(|wave| wave.needs_band_limited_table()) else {
+                return
+            };
+            (wave.resample(band_limited::TABLE_LEN), wave.shape_version)
+        };
+        let table = Arc::new(BandLimitedTable::from_cycle(&cycle));
+        // the shape could have been changed again in the meantime, then the table is already out of date
+        if let Some(wave) = self.state().wave.as_mut().filter(|wave| wave.shape_version == shape_version) {
+            wave.band_limited_table = Some(table);
+        }
+    }
+
+    // adds a node to whichever shape is being edited
     pub fn add_node(&mut self, wave_pos:f32, amplitude:f32){
-        let mut wave_initialized = match self.state().wave {
-            None => false,
-            Some(_) => true
+        self.edit_shape(|state| {
+            let edited = state.edited();
+            let mut wave_initialized = match edited {
+                None => false,
+                Some(_) => true
+            };
+            match wave_initialized {
+                true => {
+                    edited.as_mut().unwrap().insert_node(WaveNode { wave_pos, amplitude, curve:SegmentCurve::Linear });
+                },
+                false => {
+                    *edited = Some(Wave::new(WaveNode { wave_pos, amplitude, curve:SegmentCurve::Linear }));
+                }
+            }
+        })
+    }
+
+    /// Renders `seconds` of the current wave into memory without an output device. The wave is copied first,
+    /// so rendering offline does not move the play head of whatever is playing on the device.
+    pub fn render_offline(&self, seconds:f32, sample_rate:u32) -> Vec<(f32, f32)> {
+        let mut offline_state = AudioState::new();
+        offline_state.wave = self.state().wave.clone();
+        offline_state.adsr = self.state().adsr;
+        offline_state.modulator = self.state().modulator.clone();
+        offline_state.modulator_settings = self.state().modulator_settings;
+        offline_state.mod_matrix = self.state().mod_matrix;
+
+        let block_len = OUTPUT_BLOCK_LEN;
+        let params = tinyaudio::OutputDeviceParameters {
+            channels_count: 2,
+            sample_rate: sample_rate as usize,
+            channel_sample_count: block_len,
         };
-        match wave_initialized {
-            true => {
-                self.state().wave.as_mut().unwrap().insert_node(WaveNode { wave_pos, amplitude });
+
+        let total_frames = (seconds.max(0.0) * sample_rate as f32) as usize;
+        let mut frames = Vec::with_capacity(total_frames + block_len);
+        let mut block = vec![(0.0f32, 0.0f32); block_len];
+        while frames.len() < total_frames {
+            offline_state.render(&mut block, params);
+            frames.extend_from_slice(&block);
+        }
+        frames.truncate(total_frames);
+        frames
+    }
+
+    /// Renders `seconds` of the current wave and writes it as a stereo RIFF/WAV file.
+    pub fn render_to_wav<W:std::io::Write>(&self, writer:&mut W, seconds:f32, sample_rate:u32, bit_depth:BitDepth) -> Result<(), Box<dyn Error>> {
+        let frames = self.render_offline(seconds, sample_rate);
+        let samples:Vec<f32> = frames.iter().flat_map(|(left, right)| [*left, *right]).collect();
+        wav::write_wav(writer, WavSpec { channels:2, sample_rate, bit_depth }, &samples)?;
+        Ok(())
+    }
+
+    /// Replaces the whole node list of the shape being edited in one go, so the audio thread never sees a half edited
+    /// list. Interpolation and pitch settings are kept, even through an empty list, which only silences the shape.
+    pub fn replace_nodes(&self, nodes:Vec<WaveNode>) {
+        let layer = self.edit_layer();
+        self.replace_layer_nodes(layer, nodes);
+    }
+
+    /// Same as [`SoundEngine::replace_nodes`] for a layer that isn't necessarily the one being edited.
+    pub fn replace_layer_nodes(&self, layer:Layer, nodes:Vec<WaveNode>) {
+        self.edit_shape(|state| {
+            let shape = state.layer_mut(layer);
+            match (shape.as_mut(), nodes.first().copied()) {
+                (Some(wave), _) => wave.set_nodes(nodes),
+                (None, Some(first)) => {
+                    let mut wave = Wave::new(first);
+                    wave.set_nodes(nodes);
+                    *shape = Some(wave);
+                },
+                (None, None) => {},
+            }
+        })
+    }
+
+    // copy of the node list of the shape being edited, empty if nothing has been drawn
+    pub fn nodes(&self) -> Vec<WaveNode> {
+        self.layer_nodes(self.edit_layer())
+    }
+
+    pub fn layer_nodes(&self, layer:Layer) -> Vec<WaveNode> {
+        self.state().layer(layer).as_ref().map(|wave| wave.node_list.clone()).unwrap_or_default()
+    }
+
+    pub fn edit_layer(&self) -> Layer {
+        self.state().edit_layer
+    }
+
+    // node editing goes to this layer from now on
+    pub fn set_edit_layer(&self, layer:Layer) {
+        self.state().edit_layer = layer;
+    }
+
+    pub fn modulator_settings(&self) -> ModulatorSettings {
+        self.state().modulator_settings
+    }
+
+    pub fn mod_matrix(&self) -> ModMatrix {
+        self.state().mod_matrix
+    }
+
+    pub fn set_mod_matrix(&self, mod_matrix:ModMatrix) {
+        self.state().mod_matrix = mod_matrix;
+    }
+
+    pub fn set_modulator_settings(&self, settings:ModulatorSettings) {
+        self.state().modulator_settings = ModulatorSettings {
+            seconds:settings.seconds.clamp(MIN_MODULATOR_SECONDS, MAX_MODULATOR_SECONDS),
+            ..settings
+        };
+    }
+
+    // the latest samples sent to the output device, oldest first
+    pub fn scope_samples(&self) -> Vec<f32> {
+        self.state().scope.snapshot()
+    }
+
+    // first few harmonics of the shape being edited, as a starting point for editing them
+    pub fn harmonics(&self) -> Option<Harmonics> {
+        let mut state = self.state();
+        let wave = state.edited().as_ref()?;
+        Some(Harmonics::from_cycle(&wave.resample(spectrum::CYCLE_SPECTRUM_LEN)))
+    }
+
+    // harmonics of the shape as drawn, at the frequency it's playing at. The FFT only runs again once the shape has
+    // changed, and like the band limited table it runs with the lock let go
+    pub fn cycle_spectrum(&self) -> Option<Vec<SpectrumBin>> {
+        let (cycle, shape_version) = {
+            let state = self.state();
+            let wave = state.drawn(Layer::Wave)?;
+            if let Some(levels) = &wave.harmonic_levels {
+                return Some(spectrum::cycle_spectrum(levels, wave.frequency, OUTPUT_SAMPLE_RATE as f32))
+            }
+            (wave.resample(spectrum::CYCLE_SPECTRUM_LEN), wave.shape_version)
+        };
+        let levels:Arc<[f32]> = spectrum::harmonic_levels(&cycle).into();
+        let mut state = self.state();
+        let wave = state.wave.as_mut()?;
+        // a shape changed in the meantime gets its own levels next time
+        if wave.shape_version == shape_version {
+            wave.harmonic_levels = Some(levels.clone());
+        }
+        Some(spectrum::cycle_spectrum(&levels, wave.frequency, OUTPUT_SAMPLE_RATE as f32))
+    }
+
+    // spectrum of what was sent to the output device most recently
+    pub fn output_spectrum(&self) -> Vec<SpectrumBin> {
+        spectrum::output_spectrum(&self.scope_samples(), OUTPUT_SAMPLE_RATE as f32)
+    }
+
+    /// Loads a single cycle wav file (or one frame of a wavetable) and fits a reduced set of nodes to it, which
+    /// replace the shape being edited. Returns the fitted nodes so the visual state can show them as anchors.
+    pub fn import_wav(&self, bytes:&[u8], frame_index:usize) -> Result<Vec<WaveNode>, Box<dyn Error>> {
+        let wav_data = wav::read_wav(bytes)?;
+        let mono = wav_data.mono();
+
+        // a wavetable is a run of equal length cycles, anything else is treated as one cycle
+        let cycle = match wav_data.wavetable_len {
+            Some(table_len) if table_len > 0 => {
+                let num_frames = mono.len() / table_len;
+                log::warn!("wavetable has {num_frames} frame(s) of {table_len} samples, importing frame {frame_index}");
+                mono.chunks_exact(table_len).nth(frame_index)
+                    .ok_or_else(|| format!("wavetable only has {num_frames} frame(s)"))?
             },
-            false => {
-                self.state().wave = Some(Wave::new(WaveNode { wave_pos, amplitude }));
+            _ => &mono[..],
+        };
+
+        // the shape of a cycle doesn't depend on the rate it was recorded at, only the pitch it played at does
+        if !cycle.is_empty() {
+            log::warn!("cycle of {} samples played at {:.2} Hz in the file ({} Hz)", cycle.len(), wav_data.sample_rate as f32 / cycle.len() as f32, wav_data.sample_rate);
+        }
+        let nodes = node_fit::fit_nodes(cycle, node_fit::DEFAULT_FIT_TOLERANCE);
+        if nodes.len() < 2 {
+            return Err("wav file is too short to fit a wave to".into());
+        }
+        self.replace_nodes(nodes.clone());
+        Ok(nodes)
+    }
+
+    // copies the current wave onto the stack of wavetable frames, returns how many frames are stacked
+    pub fn stack_wavetable_frame(&self) -> usize {
+        let mut state = self.state();
+        if let Some(wave) = state.drawn(Layer::Wave).cloned() {
+            state.wavetable_frames.push(wave);
+        }
+        state.wavetable_frames.len()
+    }
+
+    pub fn clear_wavetable_frames(&self) {
+        self.state().wavetable_frames.clear();
+    }
+
+    /// Writes the stacked frames as a multi frame wavetable, or just the current wave if nothing has been stacked.
+    /// Every frame is resampled to `table_len` samples. Returns the number of frames written.
+    pub fn export_wavetable<W:std::io::Write>(&self, writer:&mut W, table_len:usize) -> Result<usize, Box<dyn Error>> {
+        let frames:Vec<Vec<f32>> = {
+            let state = self.state();
+            match state.wavetable_frames.is_empty() {
+                true => state.drawn(Layer::Wave).iter().map(|wave| wave.resample(table_len)).collect(),
+                false => state.wavetable_frames.iter().map(|wave| wave.resample(table_len)).collect(),
             }
+        };
+        if frames.is_empty() {
+            return Err("there is no wave to export yet".into());
+        }
+
+        wavetable::write_wavetable(writer, &frames, table_len)?;
+        Ok(frames.len())
+    }
+
+    // removes a node from the shape being edited, removing the last one leaves the shape silent but keeps its settings
+    // for when something is drawn again
+    pub fn remove_node(&self, node_index:usize) -> Option<WaveNode> {
+        self.edit_shape(|state| state.edited().as_mut()?.remove_node(node_index))
+    }
+
+    // moves a node while the wave keeps playing, returns the index the node ended up at
+    pub fn move_node(&self, node_index:usize, wave_pos:f32, amplitude:f32) -> Option<usize> {
+        self.edit_shape(|state| state.edited().as_mut()?.move_node(node_index, wave_pos, amplitude))
+    }
+
+    // switches the shape being edited between band limited playback and evaluating the nodes directly, returns
+    // whether it's now band limited
+    pub fn toggle_band_limited(&self) -> Option<bool> {
+        self.edit_shape(|state| {
+            let wave = state.edited().as_mut()?;
+            wave.band_limited = !wave.band_limited;
+            Some(wave.band_limited)
+        })
+    }
+
+    pub fn set_layer_band_limited(&self, layer:Layer, band_limited:bool) {
+        self.edit_shape(|state| {
+            if let Some(wave) = state.layer_mut(layer).as_mut() {
+                wave.band_limited = band_limited;
+            }
+        })
+    }
+
+    pub fn layer_band_limited(&self, layer:Layer) -> Option<bool> {
+        self.state().layer(layer).as_ref().map(|wave| wave.band_limited)
+    }
+
+    // of the shape being edited
+    pub fn band_limited(&self) -> Option<bool> {
+        self.layer_band_limited(self.edit_layer())
+    }
+
+    pub fn layer_interpolation_mode(&self, layer:Layer) -> Option<InterpolationMode> {
+        self.state().layer(layer).as_ref().map(|wave| wave.interpolation)
+    }
+
+    pub fn set_layer_interpolation_mode(&self, layer:Layer, mode:InterpolationMode) {
+        self.edit_shape(|state| {
+            if let Some(wave) = state.layer_mut(layer).as_mut() {
+                wave.set_interpolation(mode);
+            }
+        })
+    }
+
+    // of the shape being edited
+    pub fn interpolation_mode(&self) -> Option<InterpolationMode> {
+        self.layer_interpolation_mode(self.edit_layer())
+    }
+
+    pub fn set_interpolation_mode(&self, mode:InterpolationMode) {
+        self.set_layer_interpolation_mode(self.edit_layer(), mode);
+    }
+
+    // frequency of the wave in Hz
+    pub fn set_frequency(&self, freq:f32) {
+        if let Some(wave) = self.state().wave.as_mut() {
+            wave.set_freq(freq);
         }
-        
     }
 
     pub fn print_node_list(&self) {
-        log::warn!("state of audio node list is now: {:?}", self.state().wave.as_ref().unwrap().node_list)
+        match self.state().edited().as_ref() {
+            Some(wave) => log::warn!("state of audio node list is now: {:?}", wave.node_list),
+            None => log::warn!("audio node list is now empty"),
+        }
+    }
+
+    // switches the technique used to fill in samples between nodes, returns the mode now in use
+    pub fn cycle_interpolation_mode(&self) -> Option<InterpolationMode> {
+        self.edit_shape(|state| {
+            let wave = state.edited().as_mut()?;
+            wave.set_interpolation(wave.interpolation.next());
+            Some(wave.interpolation)
+        })
+    }
+
+    // switches the curve of the segment that starts at the node at node_index to the next kind, returns the new curve
+    pub fn cycle_node_curve(&self, node_index:usize) -> Option<SegmentCurve> {
+        self.edit_shape(|state| state.edited().as_mut()?.update_node_curve(node_index, |curve| curve.next()))
+    }
+
+    // bends the curve of the segment that starts at the node at node_index, returns the new curve
+    pub fn adjust_node_curve_tension(&self, node_index:usize, delta:f32) -> Option<SegmentCurve> {
+        self.edit_shape(|state| state.edited().as_mut()?.update_node_curve(node_index, |curve| curve.with_tension_delta(delta)))
+    }
+
+    // transposes the wave by some number of cents, returns the new pitch
+    pub fn apply_cents_to_pitch(&self, cents:f32) -> Option<Pitch> {
+        self.state().set_new_pitch_from_delta(cents)
+    }
+
+    // a key went down, a voice starts playing the wave at its pitch and at a level set by the velocity (1 to 127)
+    // the wave stops playing on its own, the keyboard has taken over
+    // returns the pitch that's playing now, for the readout
+    pub fn note_on(&self, note:u8, velocity:u8) -> Option<Pitch> {
+        let mut state = self.state();
+        state.voices.note_on(note, Pitch::from_note(note as f32).frequency(), velocity as f32 / 127.0);
+        state.drone_envelope.gate_off();
+        state.playing_pitch()
+    }
+
+    pub fn note_off(&self, note:u8) -> Option<Pitch> {
+        let mut state = self.state();
+        state.voices.note_off(note);
+        state.playing_pitch()
+    }
+
+    pub fn all_notes_off(&self) {
+        self.state().voices.all_notes_off();
+    }
+
+    pub fn set_pitch_bend(&self, cents:f32) -> Option<Pitch> {
+        let mut state = self.state();
+        state.pitch_bend_cents = cents;
+        state.playing_pitch()
+    }
+
+    // opens or closes the gate of the wave playing on its own, returns whether it's open now
+    pub fn toggle_drone_gate(&self) -> bool {
+        let mut state = self.state();
+        match state.drone_envelope.is_gate_open() {
+            true => state.drone_envelope.gate_off(),
+            false => {
+                state.drone_envelope.gate_on();
+                state.drone_modulation.restart();
+            },
+        }
+        state.drone_envelope.is_gate_open()
+    }
+
+    pub fn adsr(&self) -> AdsrSettings {
+        self.state().adsr
+    }
+
+    pub fn set_adsr(&self, adsr:AdsrSettings) {
+        self.state().adsr = adsr;
+    }
+
+    pub fn max_voices(&self) -> usize {
+        self.state().voices.max_voices()
+    }
+
+    pub fn set_max_voices(&self, max_voices:usize) {
+        self.state().voices.set_max_voices(max_voices);
+    }
+
+    pub fn steal_policy(&self) -> StealPolicy {
+        self.state().voices.steal_policy()
+    }
+
+    pub fn set_steal_policy(&self, steal_policy:StealPolicy) {
+        self.state().voices.set_steal_policy(steal_policy);
+    }
+
+    // what is being heard right now, the newest note if a keyboard is playing
+    pub fn playing_pitch(&self) -> Option<Pitch> {
+        self.state().playing_pitch()
+    }
+
+    pub fn set_vibrato_depth(&self, cents:f32) {
+        if let Some(wave) = self.state().wave.as_mut() {
+            wave.vibrato_depth_cents = cents;
+        }
     }
 
-    pub fn apply_delta_to_frequency(&self, delta:f32){
-        self.state().set_new_freq_from_delta(delta);
+    pub fn pitch(&self) -> Option<Pitch> {
+        self.state().drawn(Layer::Wave).map(|wave| Pitch::from_frequency(wave.frequency))
     }
 
     pub fn handle_audio_maintenance_events(&mut self, event: &Event<()>, control_flow: &mut ControlFlow){
@@ -0,0 +1,61 @@
+// Anti-aliased playback of a drawn cycle
+// Sharp corners in a wave are made of harmonics that go on forever, once the higher ones land above the Nyquist
+// frequency they fold back down as inharmonic noise. To avoid that the cycle is turned into harmonics with an FFT and
+// rebuilt several times, each copy ("level") keeping half as many harmonics as the one before it. Playback then picks
+// the most detailed level whose highest harmonic still fits under Nyquist at the frequency being played.
+
+use super::fft;
+
+// length of every level, 2048 samples holds up to 1023 harmonics
+pub const TABLE_LEN:usize = 2048;
+
+pub struct BandLimitedTable {
+    // levels[k] keeps harmonics up to (TABLE_LEN / 2) >> k, the last level is a pure sine
+    levels:Vec<Vec<f32>>,
+}
+
+impl BandLimitedTable {
+    // builds every level from one cycle that is TABLE_LEN samples long
+    pub fn from_cycle(cycle:&[f32]) -> Self {
+        assert_eq!(cycle.len(), TABLE_LEN, "band limited tables are built from {TABLE_LEN} sample cycles");
+        let bins = fft::real_fft(cycle);
+
+        let mut levels = vec![];
+        let mut max_harmonic = TABLE_LEN / 2;
+        while max_harmonic >= 1 {
+            // zero every bin above the cutoff, along with its mirror image in the negative frequencies
+            let mut level_bins = bins.clone();
+            for (harmonic, bin) in level_bins.iter_mut().enumerate() {
+                let freq_index = harmonic.min(TABLE_LEN - harmonic);
+                if freq_index > max_harmonic || freq_index == TABLE_LEN / 2 {
+                    *bin = fft::Complex::default();
+                }
+            }
+            levels.push(fft::real_ifft(level_bins));
+            max_harmonic /= 2;
+        }
+
+        BandLimitedTable { levels }
+    }
+
+    // the most detailed level that doesn't alias when the cycle repeats at frequency
+    pub fn level_for(&self, frequency:f32, sample_rate:f32) -> &[f32] {
+        let harmonics_that_fit = (sample_rate / 2.0 / frequency.max(f32::MIN_POSITIVE)) as usize;
+        let mut max_harmonic = TABLE_LEN / 2;
+        for level in &self.levels {
+            if max_harmonic <= harmonics_that_fit {
+                return level
+            }
+            max_harmonic /= 2;
+        }
+        self.levels.last().unwrap()
+    }
+
+    // reads a level at a position in the cycle (0.0 to 1.0), linearly interpolating between samples
+    pub fn read(level:&[f32], phase:f32) -> f32 {
+        let pos = phase.rem_euclid(1.0) * TABLE_LEN as f32;
+        let index = pos as usize % TABLE_LEN;
+        let frac = pos - pos.floor();
+        level[index] * (1.0 - frac) + level[(index + 1) % TABLE_LEN] * frac
+    }
+}
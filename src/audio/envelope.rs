@@ -0,0 +1,241 @@
+// Attack/decay/sustain/release level for a voice, stepped once per sample while the voice plays
+// A gate opening starts the attack from wherever the level is, so retriggering a note that is still sounding doesn't
+// click, and a gate closing starts the release from wherever the level is.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EnvelopeCurve {
+    // straight ramps, each stage takes exactly its time
+    Linear,
+    // quick to start and slow to settle like an analog envelope, each stage takes about its time
+    Exponential,
+}
+
+impl EnvelopeCurve {
+    pub const ALL:[EnvelopeCurve; 2] = [EnvelopeCurve::Linear, EnvelopeCurve::Exponential];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnvelopeCurve::Linear => "linear",
+            EnvelopeCurve::Exponential => "exponential",
+        }
+    }
+
+    pub fn next(&self) -> EnvelopeCurve {
+        let index = EnvelopeCurve::ALL.iter().position(|curve| curve == self).unwrap_or(0);
+        EnvelopeCurve::ALL[(index + 1) % EnvelopeCurve::ALL.len()]
+    }
+}
+
+// shared by every voice, so changes are heard on notes that are already playing
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdsrSettings {
+    // seconds
+    pub attack:f32,
+    pub decay:f32,
+    // level held while the gate stays open, 0 to 1
+    pub sustain:f32,
+    pub release:f32,
+    pub curve:EnvelopeCurve,
+}
+
+impl Default for AdsrSettings {
+    fn default() -> Self {
+        AdsrSettings { attack:0.01, decay:0.2, sustain:0.7, release:0.3, curve:EnvelopeCurve::Exponential }
+    }
+}
+
+// the exponential stages aim past where they stop by this much, otherwise they would only ever get close.
+// The attack aims well past the top, which keeps it close to a straight line the way analog attacks are
+const ATTACK_OVERSHOOT:f32 = 0.3;
+const DECAY_OVERSHOOT:f32 = 0.001;
+// a stage is over once the level is this close to where it's going, far too quiet a difference to hear
+const LEVEL_EPSILON:f32 = 1e-5;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    // from the level the gate closed at
+    Release { from:f32 },
+    Done,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Envelope {
+    stage:Stage,
+    level:f32,
+    // samples since the stage started
+    elapsed:f32,
+}
+
+impl Envelope {
+    // silent until the gate opens
+    pub fn new() -> Self {
+        Envelope { stage:Stage::Done, level:0.0, elapsed:0.0 }
+    }
+
+    pub fn gate_on(&mut self) {
+        self.enter(Stage::Attack);
+    }
+
+    // a release that is already under way carries on where it is rather than starting over
+    pub fn gate_off(&mut self) {
+        if self.is_gate_open() {
+            self.enter(Stage::Release { from:self.level });
+        }
+    }
+
+    fn enter(&mut self, stage:Stage) {
+        self.stage = stage;
+        self.elapsed = 0.0;
+    }
+
+    pub fn is_gate_open(&self) -> bool {
+        matches!(self.stage, Stage::Attack | Stage::Decay | Stage::Sustain)
+    }
+
+    // finished its release, the voice can go
+    pub fn is_done(&self) -> bool {
+        self.stage == Stage::Done
+    }
+
+    // the level the last sample was at
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    // the level for the next sample
+    pub fn next(&mut self, settings:&AdsrSettings, sample_rate:f32) -> f32 {
+        match self.stage {
+            Stage::Attack => {
+                if self.step(1.0, 1.0, ATTACK_OVERSHOOT, settings.attack, settings.curve, sample_rate) {
+                    self.level = 1.0;
+                    self.enter(Stage::Decay);
+                }
+            },
+            Stage::Decay => {
+                let sustain = settings.sustain.clamp(0.0, 1.0);
+                if self.step(sustain, 1.0 - sustain, DECAY_OVERSHOOT, settings.decay, settings.curve, sample_rate) {
+                    self.level = sustain;
+                    self.enter(Stage::Sustain);
+                }
+            },
+            // follows the setting, so turning the sustain up or down is heard straight away
+            Stage::Sustain => self.level = settings.sustain.clamp(0.0, 1.0),
+            Stage::Release { from } => {
+                if self.step(0.0, from, DECAY_OVERSHOOT, settings.release, settings.curve, sample_rate) {
+                    self.level = 0.0;
+                    self.enter(Stage::Done);
+                }
+            },
+            Stage::Done => self.level = 0.0,
+        }
+        self.level
+    }
+
+    // moves the level a sample's worth towards target, for a stage that covers span in seconds. Returns whether the
+    // stage is over, which it is once its time is up even if the level isn't quite there: a short span can leave the
+    // exponential curve's per sample step rounding away to nothing
+    fn step(&mut self, target:f32, span:f32, overshoot:f32, seconds:f32, curve:EnvelopeCurve, sample_rate:f32) -> bool {
+        let samples = (seconds * sample_rate).max(1.0);
+        let direction = if target >= self.level { 1.0 } else { -1.0 };
+        match curve {
+            EnvelopeCurve::Linear => {
+                self.level += direction * span.max(f32::EPSILON) / samples;
+            },
+            EnvelopeCurve::Exponential => {
+                // each sample closes the same fraction of the gap to a target past the end of the stage, sized so that
+                // the end of the stage is reached after the stage's time
+                let aim = target + direction * overshoot;
+                let span = span.max(f32::EPSILON);
+                let coef = (-((span + overshoot) / overshoot).ln() / samples).exp();
+                self.level = aim + (self.level - aim) * coef;
+            },
+        }
+        self.elapsed += 1.0;
+        self.elapsed >= samples || (target - self.level) * direction <= LEVEL_EPSILON
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE:f32 = 48000.0;
+
+    fn settings(curve:EnvelopeCurve) -> AdsrSettings {
+        AdsrSettings { attack:0.01, decay:0.05, sustain:0.5, release:0.2, curve }
+    }
+
+    // samples it takes to finish, None if it's still going after limit of them
+    fn samples_to_done(envelope:&mut Envelope, settings:&AdsrSettings, limit:usize) -> Option<usize> {
+        (1..=limit).find(|_| {
+            envelope.next(settings, SAMPLE_RATE);
+            envelope.is_done()
+        })
+    }
+
+    fn release_limit(settings:&AdsrSettings) -> usize {
+        (settings.release * SAMPLE_RATE) as usize + 1
+    }
+
+    #[test]
+    fn release_finishes_in_its_time() {
+        for curve in EnvelopeCurve::ALL {
+            let settings = settings(curve);
+            let mut envelope = Envelope::new();
+            envelope.gate_on();
+            for _ in 0..(0.1 * SAMPLE_RATE) as usize {
+                envelope.next(&settings, SAMPLE_RATE);
+            }
+            assert_eq!(envelope.level(), 0.5, "{} envelope should be sustaining", curve.name());
+            envelope.gate_off();
+            assert!(samples_to_done(&mut envelope, &settings, release_limit(&settings)).is_some(), "{} release never finished", curve.name());
+            assert_eq!(envelope.level(), 0.0);
+        }
+    }
+
+    #[test]
+    fn release_from_almost_silent_finishes() {
+        for curve in EnvelopeCurve::ALL {
+            let settings = AdsrSettings { attack:10.0, ..settings(curve) };
+            let mut envelope = Envelope::new();
+            envelope.gate_on();
+            envelope.next(&settings, SAMPLE_RATE);
+            assert!(envelope.level() < 1e-3);
+            envelope.gate_off();
+            assert!(samples_to_done(&mut envelope, &settings, release_limit(&settings)).is_some(), "{} release never finished", curve.name());
+        }
+    }
+
+    #[test]
+    fn closing_the_gate_again_doesnt_restart_the_release() {
+        for curve in EnvelopeCurve::ALL {
+            let settings = settings(curve);
+            let mut envelope = Envelope::new();
+            envelope.gate_on();
+            for _ in 0..(0.1 * SAMPLE_RATE) as usize {
+                envelope.next(&settings, SAMPLE_RATE);
+            }
+            envelope.gate_off();
+            let halfway = release_limit(&settings) / 2;
+            for _ in 0..halfway {
+                envelope.next(&settings, SAMPLE_RATE);
+            }
+            let level = envelope.level();
+            // as all_notes_off does to a voice that is already releasing
+            envelope.gate_off();
+            assert_eq!(envelope.level(), level);
+            let remaining = release_limit(&settings) - halfway;
+            assert!(samples_to_done(&mut envelope, &settings, remaining).is_some(), "{} release never finished", curve.name());
+        }
+    }
+
+    #[test]
+    fn closing_a_closed_gate_does_nothing() {
+        let mut envelope = Envelope::new();
+        envelope.gate_off();
+        assert!(envelope.is_done());
+    }
+}
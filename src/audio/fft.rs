@@ -0,0 +1,98 @@
+// Small in-place radix-2 FFT, enough for turning single cycles into harmonics and back
+// Lengths must be powers of two, which all of the table lengths used by the engine are
+
+use std::f32::consts::PI;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Complex {
+    pub re:f32,
+    pub im:f32,
+}
+
+impl Complex {
+    pub fn new(re:f32, im:f32) -> Self {
+        Complex { re, im }
+    }
+
+    // builds a complex number from a magnitude and a phase in radians
+    pub fn from_polar(magnitude:f32, phase:f32) -> Self {
+        Complex { re:magnitude * phase.cos(), im:magnitude * phase.sin() }
+    }
+
+    pub fn magnitude(&self) -> f32 {
+        self.re.hypot(self.im)
+    }
+
+    // angle in radians, -pi to pi
+    pub fn phase(&self) -> f32 {
+        self.im.atan2(self.re)
+    }
+
+    fn mul(self, other:Complex) -> Complex {
+        Complex::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+    }
+}
+
+// forward transform, bin k of the result holds the k-th harmonic of the cycle (unnormalized)
+pub fn fft(data:&mut [Complex]) {
+    transform(data, false);
+}
+
+// inverse transform, scaled by 1/n so that ifft(fft(x)) == x
+pub fn ifft(data:&mut [Complex]) {
+    transform(data, true);
+    let scale = 1.0 / data.len() as f32;
+    for value in data.iter_mut() {
+        value.re *= scale;
+        value.im *= scale;
+    }
+}
+
+// forward transform of real samples, returns every bin
+pub fn real_fft(samples:&[f32]) -> Vec<Complex> {
+    let mut data:Vec<Complex> = samples.iter().map(|sample| Complex::new(*sample, 0.0)).collect();
+    fft(&mut data);
+    data
+}
+
+// inverse transform back to real samples, the bins are expected to be conjugate symmetric
+pub fn real_ifft(mut bins:Vec<Complex>) -> Vec<f32> {
+    ifft(&mut bins);
+    bins.iter().map(|bin| bin.re).collect()
+}
+
+fn transform(data:&mut [Complex], inverse:bool) {
+    let len = data.len();
+    assert!(len.is_power_of_two(), "fft length must be a power of two, got {len}");
+
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..len {
+        let mut bit = len >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    // butterflies, doubling the size of the sub transforms each pass
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut size = 2;
+    while size <= len {
+        for k in 0..size / 2 {
+            // twiddles are computed directly rather than accumulated so the error doesn't build up on long tables
+            let twiddle = Complex::from_polar(1.0, sign * 2.0 * PI * k as f32 / size as f32);
+            for start in (0..len).step_by(size) {
+                let even = data[start + k];
+                let odd = data[start + k + size / 2].mul(twiddle);
+                data[start + k] = Complex::new(even.re + odd.re, even.im + odd.im);
+                data[start + k + size / 2] = Complex::new(even.re - odd.re, even.im - odd.im);
+            }
+        }
+        size <<= 1;
+    }
+}
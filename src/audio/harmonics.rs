@@ -0,0 +1,92 @@
+// Additive description of a cycle: the amplitude and phase of each harmonic, each one a sine wave
+// a * sin(2pi * n * t + phase) where n is the harmonic number (1 is the fundamental). Edited as sliders and turned
+// back into a dense node list with an inverse FFT, so the rest of the engine never has to know the shape came from
+// partials.
+
+use std::f32::consts::PI;
+
+use super::fft::{self, Complex};
+use super::{SegmentCurve, WaveNode};
+
+pub const NUM_HARMONICS:usize = 16;
+// nodes per cycle, 16 per cycle of the highest harmonic so even straight segments follow it closely
+const HARMONIC_NODE_COUNT:usize = 256;
+// harmonics quieter than this are treated as missing
+const SILENT_AMPLITUDE:f32 = 1e-4;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Harmonics {
+    // index 0 is the fundamental
+    amplitudes:[f32; NUM_HARMONICS],
+    // radians in -pi to pi
+    phases:[f32; NUM_HARMONICS],
+}
+
+impl Harmonics {
+    // the first NUM_HARMONICS harmonics of a cycle, anything above them is dropped
+    // the cycle length must be a power of two with room for all of them
+    pub fn from_cycle(cycle:&[f32]) -> Self {
+        let len = cycle.len();
+        assert!(len > NUM_HARMONICS * 2, "a {len} sample cycle can't hold {NUM_HARMONICS} harmonics");
+        let bins = fft::real_fft(cycle);
+        let mut harmonics = Harmonics::default();
+        for index in 0..NUM_HARMONICS {
+            let bin = bins[index + 1];
+            harmonics.amplitudes[index] = (2.0 * bin.magnitude() / len as f32).min(1.0);
+            // a sine lands a quarter turn behind a cosine in the bins, the phase of a silent harmonic is just noise
+            if harmonics.amplitudes[index] > SILENT_AMPLITUDE {
+                harmonics.phases[index] = wrap_phase(bin.phase() + PI / 2.0);
+            }
+        }
+        harmonics
+    }
+
+    pub fn amplitude(&self, index:usize) -> f32 {
+        self.amplitudes[index]
+    }
+
+    pub fn phase(&self, index:usize) -> f32 {
+        self.phases[index]
+    }
+
+    pub fn set_amplitude(&mut self, index:usize, amplitude:f32) {
+        if let Some(slot) = self.amplitudes.get_mut(index) {
+            *slot = amplitude.clamp(0.0, 1.0);
+        }
+    }
+
+    pub fn set_phase(&mut self, index:usize, phase:f32) {
+        if let Some(slot) = self.phases.get_mut(index) {
+            *slot = wrap_phase(phase);
+        }
+    }
+
+    // sum of the harmonics sampled len times over one cycle, len must be a power of two
+    pub fn to_cycle(&self, len:usize) -> Vec<f32> {
+        let mut bins = vec![Complex::default(); len];
+        for index in 0..NUM_HARMONICS.min(len / 2 - 1) {
+            let bin = Complex::from_polar(self.amplitudes[index] * len as f32 / 2.0, self.phases[index] - PI / 2.0);
+            bins[index + 1] = bin;
+            // mirror image in the negative frequencies, so the inverse comes out real
+            bins[len - index - 1] = Complex::new(bin.re, -bin.im);
+        }
+        fft::real_ifft(bins)
+    }
+
+    // evenly spaced straight segments through the sum of the harmonics
+    // harmonics can add up to more than full scale, the whole cycle is turned down when they do so the nodes stay
+    // within -1 to 1 without changing shape
+    pub fn to_nodes(&self) -> Vec<WaveNode> {
+        let cycle = self.to_cycle(HARMONIC_NODE_COUNT);
+        let peak = cycle.iter().fold(0.0f32, |peak, amplitude| peak.max(amplitude.abs()));
+        let scale = if peak > 1.0 { 1.0 / peak } else { 1.0 };
+        cycle.iter()
+            .enumerate()
+            .map(|(index, amplitude)| WaveNode::new(index as f32 / HARMONIC_NODE_COUNT as f32, amplitude * scale, SegmentCurve::Linear))
+            .collect()
+    }
+}
+
+fn wrap_phase(phase:f32) -> f32 {
+    (phase + PI).rem_euclid(2.0 * PI) - PI
+}
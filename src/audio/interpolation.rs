@@ -0,0 +1,186 @@
+// Techniques for filling in the samples that fall between two nodes of a wave
+// Every mode works on a window of four cyclically neighboring nodes so that the smooth modes can look at the
+// slope on either side of the segment being generated, the simple modes just ignore the outer two nodes
+
+use std::f32::consts::PI;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InterpolationMode {
+    Step,
+    Linear,
+    Cosine,
+    CubicHermite,
+    CatmullRom,
+    MonotoneCubic,
+}
+
+impl InterpolationMode {
+    pub const ALL:[InterpolationMode; 6] = [
+        InterpolationMode::Step,
+        InterpolationMode::Linear,
+        InterpolationMode::Cosine,
+        InterpolationMode::CubicHermite,
+        InterpolationMode::CatmullRom,
+        InterpolationMode::MonotoneCubic,
+    ];
+
+    // returns the mode after this one, wraps back around to the first, used for cycling through modes with a key
+    pub fn next(&self) -> InterpolationMode {
+        let index = Self::ALL.iter().position(|mode| mode == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InterpolationMode::Step => "step",
+            InterpolationMode::Linear => "linear",
+            InterpolationMode::Cosine => "cosine",
+            InterpolationMode::CubicHermite => "cubic hermite",
+            InterpolationMode::CatmullRom => "catmull-rom",
+            InterpolationMode::MonotoneCubic => "monotone cubic",
+        }
+    }
+
+    // the number the wave card shader's interpolate() switches on, the shader mirrors interpolate() below so the card
+    // is drawn the way the wave sounds, keep the two in sync
+    pub fn gpu_kind(&self) -> u32 {
+        match self {
+            InterpolationMode::Linear => 0,
+            InterpolationMode::Step => 1,
+            InterpolationMode::Cosine => 2,
+            InterpolationMode::CubicHermite => 3,
+            InterpolationMode::CatmullRom => 4,
+            InterpolationMode::MonotoneCubic => 5,
+        }
+    }
+
+    // calculates the value of the segment between points[1] and points[2] at progress t (0.0 to 1.0)
+    // each point is (wave position, amplitude), positions must already be unwrapped so that they are increasing
+    pub fn interpolate(&self, points:[(f32, f32); 4], t:f32) -> f32 {
+        let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = points;
+        let seg_len = x2 - x1;
+
+        match self {
+            InterpolationMode::Step => y1,
+            InterpolationMode::Linear => y1 + (y2 - y1) * t,
+            InterpolationMode::Cosine => {
+                let mu = (1.0 - (t * PI).cos()) / 2.0;
+                y1 + (y2 - y1) * mu
+            },
+            InterpolationMode::CubicHermite => {
+                // tangent at each node is the average of the slopes of the segments on either side of it
+                let m1 = (secant((x0, y0), (x1, y1)) + secant((x1, y1), (x2, y2))) / 2.0;
+                let m2 = (secant((x1, y1), (x2, y2)) + secant((x2, y2), (x3, y3))) / 2.0;
+                hermite(y1, y2, m1 * seg_len, m2 * seg_len, t)
+            },
+            InterpolationMode::CatmullRom => {
+                // tangent at each node is the slope of the chord between its two neighbors
+                let m1 = secant((x0, y0), (x2, y2));
+                let m2 = secant((x1, y1), (x3, y3));
+                hermite(y1, y2, m1 * seg_len, m2 * seg_len, t)
+            },
+            InterpolationMode::MonotoneCubic => {
+                // Fritsch-Carlson: start from averaged tangents, then limit them so the curve never overshoots the nodes
+                let d0 = secant((x0, y0), (x1, y1));
+                let d1 = secant((x1, y1), (x2, y2));
+                let d2 = secant((x2, y2), (x3, y3));
+
+                let mut m1 = if d0 * d1 <= 0.0 { 0.0 } else { (d0 + d1) / 2.0 };
+                let mut m2 = if d1 * d2 <= 0.0 { 0.0 } else { (d1 + d2) / 2.0 };
+
+                if d1 == 0.0 {
+                    m1 = 0.0;
+                    m2 = 0.0;
+                } else {
+                    let alpha = m1 / d1;
+                    let beta = m2 / d1;
+                    let mag = alpha * alpha + beta * beta;
+                    if mag > 9.0 {
+                        let tau = 3.0 / mag.sqrt();
+                        m1 = tau * alpha * d1;
+                        m2 = tau * beta * d1;
+                    }
+                }
+                hermite(y1, y2, m1 * seg_len, m2 * seg_len, t)
+            },
+        }
+    }
+}
+
+// slope between two points, flat if they share a position so that degenerate node lists don't produce NaN
+fn secant(start:(f32, f32), end:(f32, f32)) -> f32 {
+    let run = end.0 - start.0;
+    if run <= 0.0 {
+        return 0.0
+    }
+    (end.1 - start.1) / run
+}
+
+// cubic hermite spline between two values, tangents are expected to already be scaled by the segment length
+fn hermite(start:f32, end:f32, start_tangent:f32, end_tangent:f32, t:f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2.0 * t3 - 3.0 * t2 + 1.0) * start
+        + (t3 - 2.0 * t2 + t) * start_tangent
+        + (-2.0 * t3 + 3.0 * t2) * end
+        + (t3 - t2) * end_tangent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // nodes on an uneven grid, so a mode that mixes up positions and amplitudes shows
+    const POINTS:[(f32, f32); 4] = [(-0.2, 0.3), (0.1, -0.5), (0.5, 0.8), (0.6, 0.1)];
+
+    #[test]
+    fn every_mode_starts_on_the_left_node() {
+        for mode in InterpolationMode::ALL {
+            assert!((mode.interpolate(POINTS, 0.0) - POINTS[1].1).abs() < 1e-6, "{} doesn't start on the left node", mode.name());
+        }
+    }
+
+    #[test]
+    fn every_mode_but_step_ends_on_the_right_node() {
+        for mode in InterpolationMode::ALL.into_iter().filter(|mode| *mode != InterpolationMode::Step) {
+            assert!((mode.interpolate(POINTS, 1.0) - POINTS[2].1).abs() < 1e-6, "{} doesn't end on the right node", mode.name());
+        }
+    }
+
+    #[test]
+    fn step_holds_the_left_node() {
+        for t in [0.0, 0.25, 0.5, 0.999, 1.0] {
+            assert_eq!(InterpolationMode::Step.interpolate(POINTS, t), POINTS[1].1);
+        }
+    }
+
+    #[test]
+    fn linear_and_cosine_meet_halfway() {
+        let halfway = (POINTS[1].1 + POINTS[2].1) / 2.0;
+        for mode in [InterpolationMode::Linear, InterpolationMode::Cosine] {
+            assert!((mode.interpolate(POINTS, 0.5) - halfway).abs() < 1e-6, "{} isn't halfway at the middle", mode.name());
+        }
+    }
+
+    #[test]
+    fn monotone_cubic_doesnt_overshoot_monotone_nodes() {
+        // a steep step between two flat stretches is where the other cubic modes overshoot
+        let rising:[(f32, f32); 4] = [(0.0, 0.0), (0.25, 0.0), (0.5, 1.0), (0.75, 1.0)];
+        let falling = [(0.0, 1.0), (0.25, 0.9), (0.3, 0.0), (0.75, -0.1)];
+        for points in [rising, falling] {
+            let (low, high) = (points[1].1.min(points[2].1), points[1].1.max(points[2].1));
+            let values = (0..=100).map(|step| InterpolationMode::MonotoneCubic.interpolate(points, step as f32 / 100.0)).collect::<Vec<_>>();
+            assert!(values.iter().all(|value| (low..=high).contains(value)), "overshot {low} to {high}: {values:?}");
+            let rising = points[2].1 > points[1].1;
+            assert!(values.windows(2).all(|pair| if rising { pair[1] >= pair[0] } else { pair[1] <= pair[0] }));
+        }
+    }
+
+    #[test]
+    fn smooth_modes_stay_finite_with_nodes_on_top_of_each_other() {
+        let stacked = [(0.1, 0.3), (0.1, -0.5), (0.5, 0.8), (0.5, 0.1)];
+        for mode in InterpolationMode::ALL {
+            assert!(mode.interpolate(stacked, 0.5).is_finite(), "{} isn't finite", mode.name());
+        }
+    }
+}
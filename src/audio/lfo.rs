@@ -0,0 +1,56 @@
+// Low frequency oscillators for the modulation matrix, each voice runs its own copy from the start of its note
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    // ramps up, then drops
+    Saw,
+    Square,
+}
+
+impl LfoShape {
+    pub const ALL:[LfoShape; 4] = [LfoShape::Sine, LfoShape::Triangle, LfoShape::Saw, LfoShape::Square];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LfoShape::Sine => "sine",
+            LfoShape::Triangle => "triangle",
+            LfoShape::Saw => "saw",
+            LfoShape::Square => "square",
+        }
+    }
+
+    pub fn next(&self) -> LfoShape {
+        let index = LfoShape::ALL.iter().position(|shape| shape == self).unwrap_or(0);
+        LfoShape::ALL[(index + 1) % LfoShape::ALL.len()]
+    }
+
+    // -1 to 1 at a phase (0.0 to 1.0), every shape but the saw starts from the middle on its way up
+    pub fn value(&self, phase:f64) -> f32 {
+        let value = match self {
+            LfoShape::Sine => (2.0 * std::f64::consts::PI * phase).sin(),
+            LfoShape::Triangle => 4.0 * ((phase + 0.75).fract() - 0.5).abs() - 1.0,
+            LfoShape::Saw => 2.0 * phase - 1.0,
+            LfoShape::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+        };
+        value as f32
+    }
+}
+
+pub const MIN_LFO_RATE:f32 = 0.01;
+pub const MAX_LFO_RATE:f32 = 50.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LfoSettings {
+    pub shape:LfoShape,
+    // cycles per second
+    pub rate:f32,
+}
+
+impl LfoSettings {
+    // moves a phase on by some number of seconds
+    pub fn advance(&self, phase:f64, seconds:f64) -> f64 {
+        (phase + self.rate.clamp(MIN_LFO_RATE, MAX_LFO_RATE) as f64 * seconds).fract()
+    }
+}
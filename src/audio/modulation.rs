@@ -0,0 +1,242 @@
+// Modulation matrix: a fixed set of routes, each taking one source (an LFO, the ADSR, or the drawn modulator) to one
+// target (pitch, level, or the amplitude or position of a single node) by some depth. Sources are read at the start of
+// every control block rather than every sample, which is plenty for anything slow enough to be called modulation.
+//
+// Pitch and level are per voice. The shape is shared by every voice though, so routes to its nodes follow the lead
+// voice (the newest held note, or the wave on its own), and the wave is played from a modulated copy of the nodes.
+
+use super::lfo::{LfoSettings, LfoShape};
+use super::modulator::{ModulatorHead, ModulatorSettings};
+use super::{Wave, WaveNode};
+
+pub const NUM_LFOS:usize = 2;
+pub const MAX_ROUTES:usize = 8;
+
+// cents a pitch route at full depth bends by when its source is at 1
+pub const PITCH_MOD_RANGE_CENTS:f32 = 1200.0;
+// modulated nodes stay at least this far from their neighbours, so they never cross over or land on one
+const MIN_NODE_GAP:f32 = 1e-4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModSource {
+    // -1 to 1
+    Lfo(usize),
+    // the voice's own ADSR level, 0 to 1
+    Envelope,
+    // the drawing on the modulator layer, as drawn. Routes from it do nothing while nothing has been drawn
+    Modulator,
+}
+
+impl ModSource {
+    pub const ALL:[ModSource; 4] = [ModSource::Lfo(0), ModSource::Lfo(1), ModSource::Envelope, ModSource::Modulator];
+
+    pub fn name(&self) -> String {
+        match self {
+            ModSource::Lfo(index) => format!("lfo-{}", index + 1),
+            ModSource::Envelope => "envelope".to_string(),
+            ModSource::Modulator => "modulator".to_string(),
+        }
+    }
+
+    pub fn next(&self) -> ModSource {
+        let index = ModSource::ALL.iter().position(|source| source == self).unwrap_or(0);
+        ModSource::ALL[(index + 1) % ModSource::ALL.len()]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModTarget {
+    // the route is switched off
+    Off,
+    // bends the voice by up to PITCH_MOD_RANGE_CENTS either way
+    Pitch,
+    // takes up to depth away from the voice's level wherever the source is below 1
+    Gain,
+    // nodes are picked by index, so a route moves on to a neighbour when nodes are added or removed before it
+    NodeAmplitude(usize),
+    // a full cycle at full depth, but never past the nodes on either side
+    NodePosition(usize),
+}
+
+impl ModTarget {
+    pub fn name(&self) -> String {
+        match self {
+            ModTarget::Off => "off".to_string(),
+            ModTarget::Pitch => "pitch".to_string(),
+            ModTarget::Gain => "gain".to_string(),
+            ModTarget::NodeAmplitude(index) => format!("node-amplitude-{index}"),
+            ModTarget::NodePosition(index) => format!("node-position-{index}"),
+        }
+    }
+
+    pub fn from_name(name:&str) -> Option<ModTarget> {
+        let node_index = |prefix:&str| name.strip_prefix(prefix).and_then(|index| index.parse().ok());
+        match name {
+            "off" => Some(ModTarget::Off),
+            "pitch" => Some(ModTarget::Pitch),
+            "gain" => Some(ModTarget::Gain),
+            _ => node_index("node-amplitude-").map(ModTarget::NodeAmplitude)
+                .or_else(|| node_index("node-position-").map(ModTarget::NodePosition)),
+        }
+    }
+
+    // the target after this one, going on to node_index when it gets to the node targets
+    pub fn next(&self, node_index:usize) -> ModTarget {
+        match self {
+            ModTarget::Off => ModTarget::Pitch,
+            ModTarget::Pitch => ModTarget::Gain,
+            ModTarget::Gain => ModTarget::NodeAmplitude(node_index),
+            ModTarget::NodeAmplitude(_) => ModTarget::NodePosition(node_index),
+            ModTarget::NodePosition(_) => ModTarget::Off,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ModRoute {
+    pub source:ModSource,
+    pub target:ModTarget,
+    // -1 to 1, negative turns the source upside down
+    pub depth:f32,
+}
+
+impl ModRoute {
+    pub const OFF:ModRoute = ModRoute { source:ModSource::Lfo(0), target:ModTarget::Off, depth:0.0 };
+}
+
+// shared by every voice, so changes are heard on notes that are already playing
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ModMatrix {
+    pub lfos:[LfoSettings; NUM_LFOS],
+    pub routes:[ModRoute; MAX_ROUTES],
+}
+
+impl Default for ModMatrix {
+    // the drawn modulator shapes the level of every voice, the way it did before there was a matrix to route it
+    fn default() -> Self {
+        let mut routes = [ModRoute::OFF; MAX_ROUTES];
+        routes[0] = ModRoute { source:ModSource::Modulator, target:ModTarget::Gain, depth:1.0 };
+        ModMatrix {
+            lfos:[
+                LfoSettings { shape:LfoShape::Sine, rate:5.0 },
+                LfoSettings { shape:LfoShape::Triangle, rate:0.5 },
+            ],
+            routes,
+        }
+    }
+}
+
+// where one voice is in each of its sources
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ModState {
+    lfo_phases:[f64; NUM_LFOS],
+    modulator_head:ModulatorHead,
+    // level the last block finished on, the next block ramps on from there so level changes don't click
+    // None before the first block
+    gain:Option<f32>,
+}
+
+impl ModState {
+    // the sources start over with a new note, the level carries on from where it was
+    pub fn restart(&mut self) {
+        self.lfo_phases = [0.0; NUM_LFOS];
+        self.modulator_head.restart();
+    }
+
+    // swaps in the level for the block that's starting, returns the level the last block finished on
+    pub fn ramp_gain(&mut self, gain:f32) -> f32 {
+        self.gain.replace(gain).unwrap_or(gain)
+    }
+}
+
+// what every source is putting out for one voice at the start of a block
+#[derive(Copy, Clone, Debug)]
+pub struct SourceValues {
+    lfos:[f32; NUM_LFOS],
+    envelope:f32,
+    modulator:Option<f32>,
+}
+
+impl SourceValues {
+    fn get(&self, source:ModSource) -> Option<f32> {
+        match source {
+            ModSource::Lfo(index) => self.lfos.get(index).copied(),
+            ModSource::Envelope => Some(self.envelope),
+            ModSource::Modulator => self.modulator,
+        }
+    }
+}
+
+impl ModMatrix {
+    pub(super) fn sources(&self, state:&ModState, envelope:f32, modulator:Option<&Wave>) -> SourceValues {
+        let mut lfos = [0.0; NUM_LFOS];
+        for ((value, lfo), phase) in lfos.iter_mut().zip(self.lfos.iter()).zip(state.lfo_phases.iter()) {
+            *value = lfo.shape.value(*phase);
+        }
+        SourceValues { lfos, envelope, modulator:modulator.map(|shape| state.modulator_head.value(shape)) }
+    }
+
+    // moves every source of a voice on by a block
+    pub(super) fn advance(&self, state:&mut ModState, modulator:&ModulatorSettings, samples:usize, sample_rate:f32) {
+        let seconds = samples as f64 / sample_rate as f64;
+        for (phase, lfo) in state.lfo_phases.iter_mut().zip(self.lfos.iter()) {
+            *phase = lfo.advance(*phase, seconds);
+        }
+        state.modulator_head.advance(modulator, seconds);
+    }
+
+    // the routes that are switched on and have something to read from, with what their source is putting out
+    fn active_routes<'a>(&'a self, values:&'a SourceValues) -> impl Iterator<Item = (&'a ModRoute, f32)> + 'a {
+        self.routes.iter()
+            .filter(|route| route.target != ModTarget::Off)
+            .filter_map(|route| values.get(route.source).map(|value| (route, value)))
+    }
+
+    pub fn pitch_cents(&self, values:&SourceValues) -> f32 {
+        self.active_routes(values)
+            .filter(|(route, _)| route.target == ModTarget::Pitch)
+            .map(|(route, value)| route.depth * value * PITCH_MOD_RANGE_CENTS)
+            .sum()
+    }
+
+    // 0 to 1, multiplied into the voice's level
+    pub fn gain(&self, values:&SourceValues) -> f32 {
+        self.active_routes(values)
+            .filter(|(route, _)| route.target == ModTarget::Gain)
+            .map(|(route, value)| {
+                let value = if route.depth < 0.0 { 1.0 - value } else { value };
+                (1.0 - route.depth.abs() * (1.0 - value)).clamp(0.0, 1.0)
+            })
+            .product()
+    }
+
+    // writes a copy of the nodes with the node routes applied into modulated, returns false if no route reaches any
+    // of them. This runs on the audio thread every block, so it works in place rather than allocating once modulated
+    // has grown to hold the nodes
+    pub(super) fn modulate_nodes(&self, nodes:&[WaveNode], values:&SourceValues, modulated:&mut Vec<WaveNode>) -> bool {
+        modulated.clear();
+        modulated.extend_from_slice(nodes);
+        let mut any_moved = false;
+        for (route, value) in self.active_routes(values) {
+            let offset = route.depth * value;
+            match route.target {
+                ModTarget::NodeAmplitude(index) if index < nodes.len() => modulated[index].amplitude += offset,
+                ModTarget::NodePosition(index) if index < nodes.len() => modulated[index].wave_pos += offset,
+                _ => continue,
+            }
+            any_moved = true;
+        }
+        if !any_moved {
+            return false
+        }
+
+        // each node is kept between the one before it (where that was moved to) and where the one after it was drawn,
+        // so the order can't change however the routes push them about
+        for index in 0..modulated.len() {
+            let lower = index.checked_sub(1).map(|prev| modulated[prev].wave_pos + MIN_NODE_GAP).unwrap_or(0.0);
+            let upper = nodes.get(index + 1).map(|next| next.wave_pos).unwrap_or(1.0) - MIN_NODE_GAP;
+            modulated[index].wave_pos = modulated[index].wave_pos.min(upper).max(lower);
+        }
+        true
+    }
+}
@@ -0,0 +1,99 @@
+// A shape drawn with the same anchors as the wave, but read slowly from start to end instead of being played as
+// sound. Each voice (and the wave playing on its own) gets a head that steps through it, so the drawing becomes
+// something like an envelope or an LFO, which the modulation matrix can route wherever an LFO could go.
+
+use super::Wave;
+
+// the shapes the anchor editor can be working on
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layer {
+    // the cycle that is heard
+    Wave,
+    // the drawn modulation source
+    Modulator,
+}
+
+impl Layer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layer::Wave => "wave",
+            Layer::Modulator => "modulator",
+        }
+    }
+
+    pub fn other(&self) -> Layer {
+        match self {
+            Layer::Wave => Layer::Modulator,
+            Layer::Modulator => Layer::Wave,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModulatorMode {
+    // runs through the drawing once from the start of a note, then holds where the drawing ends (which is where
+    // it joins back up with its start)
+    OneShot,
+    // goes round and round like an LFO, from the start of a note
+    Loop,
+}
+
+impl ModulatorMode {
+    pub const ALL:[ModulatorMode; 2] = [ModulatorMode::OneShot, ModulatorMode::Loop];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModulatorMode::OneShot => "one-shot",
+            ModulatorMode::Loop => "loop",
+        }
+    }
+
+    pub fn next(&self) -> ModulatorMode {
+        let index = ModulatorMode::ALL.iter().position(|mode| mode == self).unwrap_or(0);
+        ModulatorMode::ALL[(index + 1) % ModulatorMode::ALL.len()]
+    }
+}
+
+pub const MIN_MODULATOR_SECONDS:f32 = 0.01;
+pub const MAX_MODULATOR_SECONDS:f32 = 60.0;
+
+// shared by every head, so changes are heard on notes that are already playing
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ModulatorSettings {
+    pub mode:ModulatorMode,
+    // how long one pass through the drawing takes
+    pub seconds:f32,
+}
+
+impl Default for ModulatorSettings {
+    fn default() -> Self {
+        ModulatorSettings { mode:ModulatorMode::OneShot, seconds:1.0 }
+    }
+}
+
+// where one voice is in the drawing
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ModulatorHead {
+    // 0.0 to 1.0 across the drawing
+    pos:f64,
+}
+
+impl ModulatorHead {
+    pub(super) fn restart(&mut self) {
+        self.pos = 0.0;
+    }
+
+    // the drawing's value where the head is
+    pub(super) fn value(&self, shape:&Wave) -> f32 {
+        shape.value_at(self.pos as f32)
+    }
+
+    // moves the head on by some number of seconds
+    pub(super) fn advance(&mut self, settings:&ModulatorSettings, seconds:f64) {
+        let step = seconds / settings.seconds.max(MIN_MODULATOR_SECONDS) as f64;
+        self.pos = match settings.mode {
+            ModulatorMode::OneShot => (self.pos + step).min(1.0),
+            ModulatorMode::Loop => (self.pos + step).fract(),
+        };
+    }
+}
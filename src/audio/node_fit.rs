@@ -0,0 +1,84 @@
+// Turns a densely sampled single cycle into a small list of nodes that traces the same shape
+// Uses Ramer-Douglas-Peucker: keep the endpoints, find the sample furthest from the straight line between them,
+// keep it if it is further than the tolerance and do the same to both halves. The halves wait on a stack of their own
+// rather than the call stack, a long or noisy file can split thousands of times deep
+
+use super::{SegmentCurve, WaveNode};
+
+// more nodes than this gets hard to edit by hand, the tolerance is loosened until the fit comes in under it
+pub const MAX_FITTED_NODES:usize = 64;
+pub const DEFAULT_FIT_TOLERANCE:f32 = 0.01;
+
+// fits nodes to one cycle of samples, the samples are assumed to be evenly spaced across the whole cycle
+pub fn fit_nodes(cycle:&[f32], tolerance:f32) -> Vec<WaveNode> {
+    if cycle.is_empty() {
+        return vec![]
+    }
+
+    let points:Vec<(f32, f32)> = cycle.iter().enumerate()
+        .map(|(index, sample)| (index as f32 / cycle.len() as f32, *sample))
+        .collect();
+
+    let mut tolerance = tolerance.max(f32::EPSILON);
+    loop {
+        let mut keep = vec![false; points.len()];
+        keep[0] = true;
+        keep[points.len() - 1] = true;
+        simplify(&points, 0, points.len() - 1, tolerance, &mut keep);
+
+        let nodes:Vec<WaveNode> = points.iter().zip(keep)
+            .filter(|(_, kept)| *kept)
+            .map(|(point, _)| WaveNode { wave_pos:point.0, amplitude:point.1, curve:SegmentCurve::Linear })
+            .collect();
+        if nodes.len() <= MAX_FITTED_NODES {
+            return nodes
+        }
+        tolerance *= 2.0;
+    }
+}
+
+fn simplify(points:&[(f32, f32)], first:usize, last:usize, tolerance:f32, keep:&mut [bool]) {
+    // spans (first and last point) still to be looked at
+    let mut spans = vec![(first, last)];
+    while let Some((first, last)) = spans.pop() {
+        if last <= first + 1 {
+            continue
+        }
+
+        // vertical distance from the line between the endpoints, amplitude error is what we hear
+        let (x0, y0) = points[first];
+        let (x1, y1) = points[last];
+        let slope = (y1 - y0) / (x1 - x0);
+        let (furthest, dist) = points[first + 1..last].iter().enumerate()
+            .map(|(offset, (x, y))| (first + 1 + offset, (y - (y0 + slope * (x - x0))).abs()))
+            .fold((first, 0.0f32), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+
+        if dist > tolerance {
+            keep[furthest] = true;
+            spans.push((first, furthest));
+            spans.push((furthest, last));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_corners_of_a_triangle() {
+        let cycle = (0..64).map(|index| 1.0 - (index as f32 - 32.0).abs() / 16.0).collect::<Vec<_>>();
+        let positions = fit_nodes(&cycle, DEFAULT_FIT_TOLERANCE).iter().map(|node| node.wave_pos).collect::<Vec<_>>();
+        assert_eq!(positions, vec![0.0, 0.5, 63.0 / 64.0]);
+    }
+
+    #[test]
+    fn splits_deeper_than_the_call_stack_goes() {
+        // a square wave at the highest frequency samples can hold, every split lands right next to the start of the
+        // span, so there are as many splits inside each other as there are samples
+        let points = (0..10_000).map(|index| (index as f32 / 10_000.0, if index % 2 == 0 { 0.5 } else { -0.5 })).collect::<Vec<_>>();
+        let mut keep = vec![false; points.len()];
+        simplify(&points, 0, points.len() - 1, DEFAULT_FIT_TOLERANCE, &mut keep);
+        assert!(keep[1..points.len() - 1].iter().all(|kept| *kept));
+    }
+}
@@ -0,0 +1,52 @@
+// Pitch the way musicians think about it: a MIDI note number where the fractional part is cents / 100
+// Equal steps in note number are equal musical steps, which is what makes tuning by wheel feel even across registers
+
+const A4_NOTE:f32 = 69.0;
+const A4_FREQUENCY:f32 = 440.0;
+const NOTE_NAMES:[&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+// range of a MIDI keyboard, C-1 to G9
+const LOWEST_NOTE:f32 = 0.0;
+const HIGHEST_NOTE:f32 = 127.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pitch {
+    note:f32,
+}
+
+impl Pitch {
+    pub fn from_note(note:f32) -> Self {
+        Pitch { note:note.clamp(LOWEST_NOTE, HIGHEST_NOTE) }
+    }
+
+    pub fn from_frequency(freq:f32) -> Self {
+        Self::from_note(A4_NOTE + 12.0 * (freq.max(f32::MIN_POSITIVE) / A4_FREQUENCY).log2())
+    }
+
+    pub fn frequency(&self) -> f32 {
+        A4_FREQUENCY * 2.0f32.powf((self.note - A4_NOTE) / 12.0)
+    }
+
+    // moves the pitch up (positive) or down (negative) by some number of cents, 100 cents to a semitone
+    pub fn transposed(&self, cents:f32) -> Pitch {
+        Self::from_note(self.note + cents / 100.0)
+    }
+
+    // nearest MIDI note
+    pub fn note(&self) -> i32 {
+        self.note.round() as i32
+    }
+
+    // how far off the nearest note the pitch is, between -50 and 50
+    pub fn cents(&self) -> f32 {
+        (self.note - self.note.round()) * 100.0
+    }
+
+    // note name with octave and the cents offset, e.g. "A4 +3c", MIDI note 60 is C4
+    pub fn name(&self) -> String {
+        let note = self.note();
+        let octave = note.div_euclid(12) - 1;
+        let name = NOTE_NAMES[note.rem_euclid(12) as usize];
+        format!("{name}{octave} {:+.0}c", self.cents())
+    }
+}
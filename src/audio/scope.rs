@@ -0,0 +1,30 @@
+// Ring buffer of the samples most recently handed to the output device, for drawing what the engine really played
+// Written from the audio callback and read by the renderer, so reads copy the samples out rather than hold on to them.
+
+pub struct ScopeBuffer {
+    samples:Vec<f32>,
+    // where the next sample goes, which is also where the oldest one is
+    write_pos:usize,
+}
+
+impl ScopeBuffer {
+    pub fn new(len:usize) -> Self {
+        ScopeBuffer { samples:vec![0.0; len.max(1)], write_pos:0 }
+    }
+
+    // keeps the left channel, the wave is the same on both sides
+    pub fn push_frames(&mut self, frames:&[(f32, f32)]) {
+        // only the tail of a buffer longer than the ring would survive anyway
+        let skip = frames.len().saturating_sub(self.samples.len());
+        for (left, _) in &frames[skip..] {
+            self.samples[self.write_pos] = *left;
+            self.write_pos = (self.write_pos + 1) % self.samples.len();
+        }
+    }
+
+    // every sample in the ring, oldest first
+    pub fn snapshot(&self) -> Vec<f32> {
+        let (newer, older) = self.samples.split_at(self.write_pos);
+        older.iter().chain(newer).copied().collect()
+    }
+}
@@ -0,0 +1,171 @@
+// Shapes the progress through the segment between a node and its right neighbor before it is interpolated
+// The same math lives in shape_progress() in shader.wgsl so the wave card is drawn with the curve we hear,
+// keep the two in sync and keep the kind numbers used by gpu_params() matching the switch in the shader
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum SegmentCurve {
+    #[default]
+    Linear,
+    // slow start, fast finish, the value is the steepness
+    Exponential(f32),
+    // fast start, slow finish, the value is the steepness
+    Logarithmic(f32),
+    // eases in and out of the segment, the value is the power of each half
+    SCurve(f32),
+    // 1D cubic bezier through 0 and 1 with two handles given as fractions of the segment height
+    Bezier(f32, f32),
+}
+
+const DEFAULT_STEEPNESS:f32 = 4.0;
+const DEFAULT_S_POWER:f32 = 3.0;
+const DEFAULT_BEZIER_HANDLES:(f32, f32) = (0.9, 0.1);
+
+impl SegmentCurve {
+    // maps the linear progress through a segment (0.0 to 1.0) to the curved progress
+    pub fn shape(&self, progress:f32) -> f32 {
+        let t = progress.clamp(0.0, 1.0);
+        match *self {
+            SegmentCurve::Linear => t,
+            SegmentCurve::Exponential(steepness) => exp_shape(t, steepness),
+            SegmentCurve::Logarithmic(steepness) => 1.0 - exp_shape(1.0 - t, steepness),
+            SegmentCurve::SCurve(power) => {
+                if t < 0.5 {
+                    0.5 * (2.0 * t).powf(power)
+                } else {
+                    1.0 - 0.5 * (2.0 * (1.0 - t)).powf(power)
+                }
+            },
+            SegmentCurve::Bezier(handle_a, handle_b) => {
+                let inv = 1.0 - t;
+                3.0 * inv * inv * t * handle_a + 3.0 * inv * t * t * handle_b + t * t * t
+            },
+        }
+    }
+
+    // next kind of curve, each starting from its default parameters, used for cycling through curves with a key
+    pub fn next(&self) -> SegmentCurve {
+        match self {
+            SegmentCurve::Linear => SegmentCurve::Exponential(DEFAULT_STEEPNESS),
+            SegmentCurve::Exponential(_) => SegmentCurve::Logarithmic(DEFAULT_STEEPNESS),
+            SegmentCurve::Logarithmic(_) => SegmentCurve::SCurve(DEFAULT_S_POWER),
+            SegmentCurve::SCurve(_) => SegmentCurve::Bezier(DEFAULT_BEZIER_HANDLES.0, DEFAULT_BEZIER_HANDLES.1),
+            SegmentCurve::Bezier(..) => SegmentCurve::Linear,
+        }
+    }
+
+    // bends the curve further (positive delta) or back towards a straight line (negative delta)
+    pub fn with_tension_delta(&self, delta:f32) -> SegmentCurve {
+        match *self {
+            SegmentCurve::Linear => SegmentCurve::Linear,
+            SegmentCurve::Exponential(steepness) => SegmentCurve::Exponential((steepness + delta).clamp(0.0, 20.0)),
+            SegmentCurve::Logarithmic(steepness) => SegmentCurve::Logarithmic((steepness + delta).clamp(0.0, 20.0)),
+            SegmentCurve::SCurve(power) => SegmentCurve::SCurve((power + delta).clamp(1.0, 20.0)),
+            SegmentCurve::Bezier(handle_a, handle_b) => SegmentCurve::Bezier(
+                (handle_a + delta * 0.1).clamp(-1.0, 2.0),
+                (handle_b - delta * 0.1).clamp(-1.0, 2.0),
+            ),
+        }
+    }
+
+    // packs the curve into the layout read by the wave card shader: [kind, param a, param b, unused]
+    pub fn gpu_params(&self) -> [f32;4] {
+        match *self {
+            SegmentCurve::Linear => [0.0, 0.0, 0.0, 0.0],
+            SegmentCurve::Exponential(steepness) => [1.0, steepness, 0.0, 0.0],
+            SegmentCurve::Logarithmic(steepness) => [2.0, steepness, 0.0, 0.0],
+            SegmentCurve::SCurve(power) => [3.0, power, 0.0, 0.0],
+            SegmentCurve::Bezier(handle_a, handle_b) => [4.0, handle_a, handle_b, 0.0],
+        }
+    }
+
+    // inverse of gpu_params(), unknown kinds come back as linear
+    pub fn from_gpu_params(params:[f32;4]) -> Self {
+        match params[0] as u32 {
+            1 => SegmentCurve::Exponential(params[1]),
+            2 => SegmentCurve::Logarithmic(params[1]),
+            3 => SegmentCurve::SCurve(params[1]),
+            4 => SegmentCurve::Bezier(params[1], params[2]),
+            _ => SegmentCurve::Linear,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SegmentCurve::Linear => "linear",
+            SegmentCurve::Exponential(_) => "exponential",
+            SegmentCurve::Logarithmic(_) => "logarithmic",
+            SegmentCurve::SCurve(_) => "s-curve",
+            SegmentCurve::Bezier(..) => "bezier",
+        }
+    }
+}
+
+// normalized exponential rise from 0 to 1, falls back to a straight line when it is too shallow to divide by
+fn exp_shape(t:f32, steepness:f32) -> f32 {
+    if steepness.abs() < 1e-3 {
+        return t
+    }
+    ((steepness * t).exp() - 1.0) / (steepness.exp() - 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one of every kind, with parameters that aren't the defaults
+    const CURVES:[SegmentCurve; 5] = [
+        SegmentCurve::Linear,
+        SegmentCurve::Exponential(2.0),
+        SegmentCurve::Logarithmic(2.0),
+        SegmentCurve::SCurve(2.0),
+        SegmentCurve::Bezier(0.75, 0.5),
+    ];
+
+    // shape_progress() in shader.wgsl has to give the same numbers
+    #[test]
+    fn shapes_the_middle_of_the_segment() {
+        let e2 = 2.0f32.exp();
+        let expected = [0.5, (e2.sqrt() - 1.0) / (e2 - 1.0), 1.0 - (e2.sqrt() - 1.0) / (e2 - 1.0), 0.5, 0.375 * (0.75 + 0.5) + 0.125];
+        for (curve, expected) in CURVES.iter().zip(expected) {
+            assert!((curve.shape(0.5) - expected).abs() < 1e-6, "{} gives {} halfway, not {expected}", curve.name(), curve.shape(0.5));
+        }
+    }
+
+    #[test]
+    fn every_curve_runs_from_0_to_1() {
+        for curve in CURVES {
+            assert!(curve.shape(0.0).abs() < 1e-6, "{} doesn't start at 0", curve.name());
+            assert!((curve.shape(1.0) - 1.0).abs() < 1e-6, "{} doesn't end at 1", curve.name());
+        }
+    }
+
+    #[test]
+    fn progress_outside_the_segment_is_clamped() {
+        for curve in CURVES {
+            assert_eq!(curve.shape(-0.5), curve.shape(0.0));
+            assert_eq!(curve.shape(1.5), curve.shape(1.0));
+        }
+    }
+
+    #[test]
+    fn a_flat_steepness_is_a_straight_line() {
+        assert_eq!(SegmentCurve::Exponential(0.0).shape(0.25), 0.25);
+        assert_eq!(SegmentCurve::Logarithmic(0.0).shape(0.25), 0.25);
+    }
+
+    // sessions and share links store curves as their gpu params
+    #[test]
+    fn round_trips_through_gpu_params() {
+        for curve in CURVES {
+            assert_eq!(SegmentCurve::from_gpu_params(curve.gpu_params()), curve);
+        }
+        assert_eq!(SegmentCurve::from_gpu_params([9.0, 1.0, 1.0, 0.0]), SegmentCurve::Linear);
+    }
+
+    #[test]
+    fn cycling_goes_through_every_kind() {
+        let mut curve = SegmentCurve::Linear;
+        let names = (0..5).map(|_| { curve = curve.next(); curve.name() }).collect::<Vec<_>>();
+        assert_eq!(names, ["exponential", "logarithmic", "s-curve", "bezier", "linear"]);
+    }
+}
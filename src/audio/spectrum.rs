@@ -0,0 +1,65 @@
+// Spectra for the analyzer panel, as levels in dB at frequencies in Hz
+// The ideal spectrum comes straight from one cycle of the shape, so every bin is exactly one harmonic. The output spectrum
+// is taken from what was actually played, windowed since the buffer doesn't hold a whole number of cycles, and shows
+// whatever the ideal one doesn't: aliasing, band limiting, glitches between buffers.
+
+use super::fft;
+
+// anything quieter is drawn at the floor
+const SPECTRUM_FLOOR_DB:f32 = -96.0;
+// samples per cycle when taking the ideal spectrum, enough for 511 harmonics
+pub const CYCLE_SPECTRUM_LEN:usize = 1024;
+// most recent output samples used for the output spectrum, about 5Hz per bin at 44.1kHz
+const OUTPUT_SPECTRUM_LEN:usize = 8192;
+
+#[derive(Copy, Clone, Debug)]
+pub struct SpectrumBin {
+    pub frequency:f32,
+    pub level_db:f32,
+}
+
+// level of each harmonic of one cycle from the first up, whatever it's played at. The cycle length must be a power of two
+pub fn harmonic_levels(cycle:&[f32]) -> Vec<f32> {
+    let len = cycle.len();
+    let bins = fft::real_fft(cycle);
+    // the DC bin is skipped, there is no 0Hz on a log axis
+    (1..len / 2).map(|harmonic| amplitude_db(2.0 * bins[harmonic].magnitude() / len as f32)).collect()
+}
+
+// the harmonics from harmonic_levels played at frequency, up to nyquist
+pub fn cycle_spectrum(harmonic_levels:&[f32], frequency:f32, sample_rate:f32) -> Vec<SpectrumBin> {
+    harmonic_levels.iter()
+        .enumerate()
+        .map(|(index, &level_db)| SpectrumBin { frequency:(index + 1) as f32 * frequency, level_db })
+        .take_while(|bin| bin.frequency < sample_rate / 2.0)
+        .collect()
+}
+
+// spectrum of the last OUTPUT_SPECTRUM_LEN samples (or the largest power of two that fits), with a Hann window
+pub fn output_spectrum(samples:&[f32], sample_rate:f32) -> Vec<SpectrumBin> {
+    let len = match samples.len() {
+        0 => return vec![],
+        available => OUTPUT_SPECTRUM_LEN.min(1 << available.ilog2()),
+    };
+    let windowed = samples[samples.len() - len..].iter()
+        .enumerate()
+        .map(|(index, sample)| sample * hann(index, len))
+        .collect::<Vec<_>>();
+    let bins = fft::real_fft(&windowed);
+    // the window halves the level of a steady tone, which is made up for so both spectra read the same
+    (1..len / 2)
+        .map(|index| SpectrumBin {
+            frequency:index as f32 * sample_rate / len as f32,
+            level_db:amplitude_db(4.0 * bins[index].magnitude() / len as f32),
+        })
+        .collect()
+}
+
+fn hann(index:usize, len:usize) -> f32 {
+    0.5 - 0.5 * (2.0 * std::f32::consts::PI * index as f32 / len as f32).cos()
+}
+
+// peak amplitude to dB relative to full scale
+fn amplitude_db(amplitude:f32) -> f32 {
+    (20.0 * amplitude.log10()).max(SPECTRUM_FLOOR_DB)
+}
@@ -0,0 +1,259 @@
+// Voices for playing the wave from a keyboard, each note gets its own play head and frequency so several can sound at
+// once. The shape is shared, only where each voice is in it differs. When every voice is taken a new note steals one.
+
+use super::envelope::Envelope;
+use super::modulation::ModState;
+
+// where one playing copy of the wave is, both kept across buffers so consecutive buffers join up without a discontinuity
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PlayHead {
+    // how far through the cycle the next sample is (0.0 to 1.0)
+    pub phase:f64,
+    // position in the vibrato's cycle (0.0 to 1.0)
+    pub vibrato_phase:f64,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Voice {
+    pub note:u8,
+    // in Hz, before the pitch wheel
+    pub frequency:f32,
+    // from how hard the key was hit
+    pub level:f32,
+    pub head:PlayHead,
+    // the voice keeps playing through its release after the key comes up
+    pub envelope:Envelope,
+    // where the voice is in each modulation source
+    pub modulation:ModState,
+    // loudest sample the voice put out in the last control block, for stealing the quietest
+    pub loudness:f32,
+    // counts up with every note, for stealing the oldest
+    started:u64,
+}
+
+// which voice a new note takes over when they're all playing
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StealPolicy {
+    Oldest,
+    Quietest,
+}
+
+impl StealPolicy {
+    pub const ALL:[StealPolicy; 2] = [StealPolicy::Oldest, StealPolicy::Quietest];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StealPolicy::Oldest => "oldest",
+            StealPolicy::Quietest => "quietest",
+        }
+    }
+
+    pub fn next(&self) -> StealPolicy {
+        let index = StealPolicy::ALL.iter().position(|policy| policy == self).unwrap_or(0);
+        StealPolicy::ALL[(index + 1) % StealPolicy::ALL.len()]
+    }
+}
+
+pub const MAX_VOICES:usize = 32;
+pub const DEFAULT_VOICES:usize = 8;
+
+pub struct VoicePool {
+    voices:Vec<Voice>,
+    max_voices:usize,
+    steal_policy:StealPolicy,
+    notes_started:u64,
+}
+
+impl VoicePool {
+    pub fn new(max_voices:usize, steal_policy:StealPolicy) -> Self {
+        VoicePool { voices:vec![], max_voices:max_voices.clamp(1, MAX_VOICES), steal_policy, notes_started:0 }
+    }
+
+    // starts a voice for the note, a note that is already playing (or still releasing) starts over in the same voice
+    // from where its envelope is
+    pub fn note_on(&mut self, note:u8, frequency:f32, level:f32) {
+        let started = self.notes_started;
+        self.notes_started += 1;
+
+        if let Some(voice) = self.voices.iter_mut().find(|voice| voice.note == note) {
+            voice.level = level;
+            voice.started = started;
+            voice.envelope.gate_on();
+            voice.modulation.restart();
+            return
+        }
+
+        let mut envelope = Envelope::new();
+        envelope.gate_on();
+        let voice = Voice { note, frequency, level, head:PlayHead::default(), envelope,
+            modulation:ModState::default(), loudness:0.0, started };
+        if self.voices.len() < self.max_voices {
+            self.voices.push(voice);
+        } else if let Some(index) = self.victim() {
+            self.voices[index] = voice;
+        }
+    }
+
+    // the note's voice starts its release
+    pub fn note_off(&mut self, note:u8) {
+        for voice in self.voices.iter_mut().filter(|voice| voice.note == note) {
+            voice.envelope.gate_off();
+        }
+    }
+
+    pub fn all_notes_off(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.envelope.gate_off();
+        }
+    }
+
+    // lets go of voices that have finished their release
+    pub fn remove_finished(&mut self) {
+        self.voices.retain(|voice| !voice.envelope.is_done());
+    }
+
+    pub fn max_voices(&self) -> usize {
+        self.max_voices
+    }
+
+    // voices past the new limit are stolen straight away
+    pub fn set_max_voices(&mut self, max_voices:usize) {
+        self.max_voices = max_voices.clamp(1, MAX_VOICES);
+        while self.voices.len() > self.max_voices {
+            if let Some(index) = self.victim() {
+                self.voices.remove(index);
+            }
+        }
+    }
+
+    pub fn steal_policy(&self) -> StealPolicy {
+        self.steal_policy
+    }
+
+    pub fn set_steal_policy(&mut self, steal_policy:StealPolicy) {
+        self.steal_policy = steal_policy;
+    }
+
+    // the held note that started most recently
+    pub fn newest(&self) -> Option<&Voice> {
+        self.voices.iter().filter(|voice| voice.envelope.is_gate_open()).max_by_key(|voice| voice.started)
+    }
+
+    pub fn voices_mut(&mut self) -> impl Iterator<Item = &mut Voice> {
+        self.voices.iter_mut()
+    }
+
+    // index of the voice to give up for a new note, notes that have been let go of are given up before held ones
+    fn victim(&self) -> Option<usize> {
+        let voices = self.voices.iter().enumerate();
+        let held = |voice:&Voice| voice.envelope.is_gate_open();
+        let victim = match self.steal_policy {
+            StealPolicy::Oldest => voices.min_by_key(|(_, voice)| (held(voice), voice.started)),
+            // ties go to the oldest, so a chord of equal voices doesn't keep stealing the newest note
+            StealPolicy::Quietest => voices.min_by(|(_, a), (_, b)| {
+                held(a).cmp(&held(b)).then(a.loudness.total_cmp(&b.loudness)).then(a.started.cmp(&b.started))
+            }),
+        };
+        victim.map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::envelope::AdsrSettings;
+
+    const SAMPLE_RATE:f32 = 48000.0;
+
+    fn pool(max_voices:usize, steal_policy:StealPolicy, notes:&[u8]) -> VoicePool {
+        let mut pool = VoicePool::new(max_voices, steal_policy);
+        for &note in notes {
+            pool.note_on(note, 440.0, 1.0);
+        }
+        pool
+    }
+
+    fn notes(pool:&VoicePool) -> Vec<u8> {
+        let mut notes = pool.voices.iter().map(|voice| voice.note).collect::<Vec<_>>();
+        notes.sort();
+        notes
+    }
+
+    #[test]
+    fn steals_the_oldest_note() {
+        let pool = pool(2, StealPolicy::Oldest, &[60, 62, 64]);
+        assert_eq!(notes(&pool), [62, 64]);
+    }
+
+    #[test]
+    fn steals_a_let_go_note_before_a_held_one() {
+        for steal_policy in StealPolicy::ALL {
+            let mut pool = pool(2, steal_policy, &[60, 62]);
+            pool.note_off(62);
+            pool.note_on(64, 440.0, 1.0);
+            assert_eq!(notes(&pool), [60, 64], "stealing the {}", steal_policy.name());
+        }
+    }
+
+    #[test]
+    fn steals_the_quietest_note() {
+        let mut pool = pool(3, StealPolicy::Quietest, &[60, 62, 64]);
+        for (voice, loudness) in pool.voices_mut().zip([0.2, 0.5, 0.1]) {
+            voice.loudness = loudness;
+        }
+        pool.note_on(65, 440.0, 1.0);
+        assert_eq!(notes(&pool), [60, 62, 65]);
+
+        // the new note is as quiet as the others, so the oldest of them goes
+        for voice in pool.voices_mut() {
+            voice.loudness = 0.0;
+        }
+        pool.note_on(67, 440.0, 1.0);
+        assert_eq!(notes(&pool), [62, 65, 67]);
+    }
+
+    #[test]
+    fn retriggering_a_note_reuses_its_voice() {
+        let mut pool = pool(2, StealPolicy::Oldest, &[60, 62]);
+        pool.note_off(60);
+        pool.note_on(60, 440.0, 0.25);
+        assert_eq!(notes(&pool), [60, 62]);
+        let newest = pool.newest().unwrap();
+        assert_eq!((newest.note, newest.level), (60, 0.25));
+        assert!(newest.envelope.is_gate_open());
+
+        // it's now the newest note, so the other one is the oldest
+        pool.note_on(64, 440.0, 1.0);
+        assert_eq!(notes(&pool), [60, 64]);
+    }
+
+    #[test]
+    fn fewer_voices_steals_the_excess() {
+        let mut pool = pool(4, StealPolicy::Oldest, &[60, 62, 64, 66]);
+        pool.note_off(64);
+        pool.set_max_voices(2);
+        assert_eq!(pool.max_voices(), 2);
+        assert_eq!(notes(&pool), [62, 66]);
+
+        pool.note_on(67, 440.0, 1.0);
+        assert_eq!(notes(&pool), [66, 67]);
+    }
+
+    #[test]
+    fn let_go_notes_free_their_voices() {
+        let settings = AdsrSettings::default();
+        let mut pool = pool(4, StealPolicy::Oldest, &[60, 62, 64, 66]);
+        pool.all_notes_off();
+        // a second of control blocks, more than the release takes, with every note let go of again each block
+        for _ in 0..(SAMPLE_RATE as usize / 64) {
+            for voice in pool.voices_mut() {
+                for _ in 0..64 {
+                    voice.envelope.next(&settings, SAMPLE_RATE);
+                }
+            }
+            pool.remove_finished();
+            pool.all_notes_off();
+        }
+        assert!(pool.voices.is_empty(), "voices still releasing: {:?}", notes(&pool));
+    }
+}
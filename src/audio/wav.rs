@@ -0,0 +1,282 @@
+// Minimal RIFF/WAV writer, just enough to get rendered waves out of the engine and into other tools
+// http://soundfile.sapp.org/doc/WaveFormat/ is a good reference for the layout of the header
+
+use std::io::{self, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl BitDepth {
+    pub fn from_bits(bits:u16) -> Option<BitDepth> {
+        match bits {
+            16 => Some(BitDepth::Int16),
+            24 => Some(BitDepth::Int24),
+            32 => Some(BitDepth::Float32),
+            _ => None,
+        }
+    }
+
+    pub fn bits(&self) -> u16 {
+        match self {
+            BitDepth::Int16 => 16,
+            BitDepth::Int24 => 24,
+            BitDepth::Float32 => 32,
+        }
+    }
+
+    fn bytes(&self) -> u16 {
+        self.bits() / 8
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct WavSpec {
+    pub channels:u16,
+    pub sample_rate:u32,
+    pub bit_depth:BitDepth,
+}
+
+const WAVE_FORMAT_PCM:u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT:u16 = 3;
+
+// writes interleaved samples (one per channel per frame) as a complete wav file
+pub fn write_wav<W:Write>(writer:&mut W, spec:WavSpec, samples:&[f32]) -> io::Result<()> {
+    write_wav_with_chunks(writer, spec, samples, &[])
+}
+
+// same as write_wav, but extra chunks (id, contents) are written between the format chunks and the sample data
+// this is where tools look for metadata like the "clm " chunk that marks a file as a wavetable
+pub fn write_wav_with_chunks<W:Write>(writer:&mut W, spec:WavSpec, samples:&[f32], extra_chunks:&[([u8;4], Vec<u8>)]) -> io::Result<()> {
+    let block_align = spec.channels * spec.bit_depth.bytes();
+    let data_len = samples.len() as u32 * spec.bit_depth.bytes() as u32;
+
+    // float data is supposed to come with an extended fmt chunk and a fact chunk
+    let is_float = spec.bit_depth == BitDepth::Float32;
+    let fmt_len:u32 = if is_float { 18 } else { 16 };
+    let fact_len:u32 = if is_float { 8 + 4 } else { 0 };
+    // chunks have to start on an even byte, odd length chunks get a padding byte after them
+    let extra_len:u32 = extra_chunks.iter().map(|(_, contents)| 8 + contents.len() as u32 + contents.len() as u32 % 2).sum();
+
+    // RIFF header, the size covers everything after these first 8 bytes
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(4 + (8 + fmt_len) + fact_len + extra_len + (8 + data_len)).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&fmt_len.to_le_bytes())?;
+    writer.write_all(&(if is_float { WAVE_FORMAT_IEEE_FLOAT } else { WAVE_FORMAT_PCM }).to_le_bytes())?;
+    writer.write_all(&spec.channels.to_le_bytes())?;
+    writer.write_all(&spec.sample_rate.to_le_bytes())?;
+    writer.write_all(&(spec.sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&spec.bit_depth.bits().to_le_bytes())?;
+    if is_float {
+        writer.write_all(&0u16.to_le_bytes())?;
+
+        writer.write_all(b"fact")?;
+        writer.write_all(&4u32.to_le_bytes())?;
+        writer.write_all(&(samples.len() as u32 / spec.channels as u32).to_le_bytes())?;
+    }
+
+    for (id, contents) in extra_chunks {
+        writer.write_all(id)?;
+        writer.write_all(&(contents.len() as u32).to_le_bytes())?;
+        writer.write_all(contents)?;
+        if contents.len() % 2 == 1 {
+            writer.write_all(&[0])?;
+        }
+    }
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    let mut data = Vec::with_capacity(data_len as usize);
+    for sample in samples {
+        let sample = sample.clamp(-1.0, 1.0);
+        match spec.bit_depth {
+            BitDepth::Int16 => data.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes()),
+            BitDepth::Int24 => data.extend_from_slice(&((sample * 8_388_607.0) as i32).to_le_bytes()[..3]),
+            BitDepth::Float32 => data.extend_from_slice(&sample.to_le_bytes()),
+        }
+    }
+    writer.write_all(&data)
+}
+
+// everything we care about from a wav file that has been read in
+pub struct WavData {
+    pub channels:u16,
+    pub sample_rate:u32,
+    // interleaved samples converted to floats between -1.0 and 1.0
+    pub samples:Vec<f32>,
+    // frame length from a "clm " chunk, only present when the file is a wavetable
+    pub wavetable_len:Option<usize>,
+}
+
+impl WavData {
+    // averages the channels of each frame down to a single channel
+    pub fn mono(&self) -> Vec<f32> {
+        let channels = self.channels.max(1) as usize;
+        self.samples.chunks(channels).map(|frame| frame.iter().sum::<f32>() / channels as f32).collect()
+    }
+}
+
+fn invalid_data(msg:&str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// parses a complete wav file, supports 8/16/24/32 bit integer PCM and 32 bit float data
+pub fn read_wav(bytes:&[u8]) -> io::Result<WavData> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid_data("not a RIFF/WAVE file"));
+    }
+
+    let mut format:Option<(u16, u16, u32, u16)> = None; // (format tag, channels, sample rate, bits per sample)
+    let mut data:Option<&[u8]> = None;
+    let mut wavetable_len = None;
+
+    // walk the chunks, skipping any we don't know about
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let len = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        // the length is whatever the file says it is, near enough to usize::MAX on wasm32 to wrap around if added blindly
+        let end = (offset + 8).checked_add(len).ok_or_else(|| invalid_data("chunk runs past the end of the file"))?;
+        let contents = bytes.get(offset + 8..end).ok_or_else(|| invalid_data("chunk runs past the end of the file"))?;
+
+        match id {
+            b"fmt " => {
+                if contents.len() < 16 {
+                    return Err(invalid_data("fmt chunk is too short"));
+                }
+                let read_u16 = |at:usize| u16::from_le_bytes([contents[at], contents[at + 1]]);
+                let mut format_tag = read_u16(0);
+                // WAVE_FORMAT_EXTENSIBLE keeps the real format tag at the start of the sub format guid
+                if format_tag == 0xFFFE && contents.len() >= 26 {
+                    format_tag = read_u16(24);
+                }
+                let sample_rate = u32::from_le_bytes(contents[4..8].try_into().unwrap());
+                format = Some((format_tag, read_u16(2), sample_rate, read_u16(14)));
+            },
+            b"data" => data = Some(contents),
+            b"clm " => {
+                // "<!>2048 ..." the frame length is the number straight after the marker
+                let text = String::from_utf8_lossy(contents);
+                wavetable_len = text.strip_prefix("<!>")
+                    .and_then(|rest| rest.split_whitespace().next())
+                    .and_then(|len| len.parse::<usize>().ok());
+            },
+            _ => {},
+        }
+        offset = end + len % 2;
+    }
+
+    let (format_tag, channels, sample_rate, bits) = format.ok_or_else(|| invalid_data("missing fmt chunk"))?;
+    let data = data.ok_or_else(|| invalid_data("missing data chunk"))?;
+    if channels == 0 {
+        return Err(invalid_data("wav file has no channels"));
+    }
+
+    let samples:Vec<f32> = match (format_tag, bits) {
+        (WAVE_FORMAT_PCM, 8) => data.iter().map(|byte| (*byte as f32 - 128.0) / 128.0).collect(),
+        (WAVE_FORMAT_PCM, 16) => data.chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0).collect(),
+        (WAVE_FORMAT_PCM, 24) => data.chunks_exact(3)
+            .map(|b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0).collect(),
+        (WAVE_FORMAT_PCM, 32) => data.chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0).collect(),
+        (WAVE_FORMAT_IEEE_FLOAT, 32) => data.chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+        _ => return Err(invalid_data(&format!("unsupported wav format {format_tag} with {bits} bits per sample"))),
+    };
+    // only float data can hold these, they would poison everything the samples are fed into
+    if samples.iter().any(|sample| !sample.is_finite()) {
+        return Err(invalid_data("wav file has samples that are infinite or not a number"));
+    }
+
+    Ok(WavData { channels, sample_rate, samples, wavetable_len })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp() -> Vec<f32> {
+        (0..64).map(|index| index as f32 / 32.0 - 1.0).collect()
+    }
+
+    fn written(bit_depth:BitDepth, samples:&[f32], extra_chunks:&[([u8;4], Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_wav_with_chunks(&mut bytes, WavSpec { channels:2, sample_rate:48000, bit_depth }, samples, extra_chunks).unwrap();
+        bytes
+    }
+
+    fn assert_round_trip(bit_depth:BitDepth, max_error:f32) {
+        let samples = ramp();
+        let read = read_wav(&written(bit_depth, &samples, &[])).unwrap();
+        assert_eq!(read.channels, 2);
+        assert_eq!(read.sample_rate, 48000);
+        assert_eq!(read.wavetable_len, None);
+        assert_eq!(read.samples.len(), samples.len());
+        for (read, written) in read.samples.iter().zip(samples.iter()) {
+            assert!((read - written).abs() <= max_error, "{} bit sample {written} came back as {read}", bit_depth.bits());
+        }
+    }
+
+    #[test]
+    fn round_trips_pcm16() {
+        assert_round_trip(BitDepth::Int16, 2.0 / 32768.0);
+    }
+
+    #[test]
+    fn round_trips_pcm24() {
+        assert_round_trip(BitDepth::Int24, 2.0 / 8_388_608.0);
+    }
+
+    #[test]
+    fn round_trips_float32() {
+        assert_round_trip(BitDepth::Float32, 0.0);
+    }
+
+    #[test]
+    fn reads_the_frame_length_of_a_wavetable() {
+        let bytes = written(BitDepth::Float32, &ramp(), &[(*b"clm ", b"<!>32 01000000 wavetable".to_vec())]);
+        assert_eq!(read_wav(&bytes).unwrap().wavetable_len, Some(32));
+    }
+
+    #[test]
+    fn odd_length_chunks_are_padded() {
+        let bytes = written(BitDepth::Int16, &ramp(), &[(*b"note", b"odd".to_vec())]);
+        assert_eq!(read_wav(&bytes).unwrap().samples.len(), ramp().len());
+    }
+
+    #[test]
+    fn rejects_truncated_chunks() {
+        let bytes = written(BitDepth::Int16, &ramp(), &[]);
+        // the data chunk is last, cutting anywhere into it or the fmt chunk leaves a chunk hanging off the end
+        for len in [bytes.len() - 1, bytes.len() - 20, 40, 24, 11] {
+            assert!(read_wav(&bytes[..len]).is_err(), "a file cut to {len} bytes should be rejected");
+        }
+    }
+
+    #[test]
+    fn rejects_a_chunk_length_past_the_end() {
+        let mut bytes = written(BitDepth::Int16, &ramp(), &[]);
+        // the fmt chunk's length
+        bytes[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_wav(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_samples_that_arent_numbers() {
+        for bad in [f32::NAN, f32::INFINITY] {
+            let mut bytes = written(BitDepth::Float32, &ramp(), &[]);
+            // the samples fill the end of the file, 4 bytes each
+            let first_sample = bytes.len() - 4 * ramp().len();
+            bytes[first_sample..first_sample + 4].copy_from_slice(&bad.to_le_bytes());
+            assert!(read_wav(&bytes).is_err());
+        }
+    }
+}
@@ -0,0 +1,29 @@
+// Single cycle wavetables in the layout Serum and Vital load: a mono 32 bit float wav where every frame is
+// exactly one cycle, all frames are the same length and a "clm " chunk says how long each frame is
+
+use std::io::{self, Write};
+
+use super::wav::{self, BitDepth, WavSpec};
+
+// table lengths synths expect, 2048 is the Serum/Vital default
+pub const SMALL_TABLE_LEN:usize = 256;
+pub const LARGE_TABLE_LEN:usize = 2048;
+
+// the sample rate doesn't affect how a wavetable plays back, this is just what the synths write themselves
+const WAVETABLE_SAMPLE_RATE:u32 = 44100;
+
+// contents of the "clm " chunk, "<!>" followed by the frame length, then flags that Serum writes for a plain table
+fn clm_chunk(table_len:usize) -> Vec<u8> {
+    format!("<!>{table_len} 01000000 wavetable (wasm-waveshaper)").into_bytes()
+}
+
+// writes each frame back to back as one wavetable file, every frame has to be table_len samples long
+pub fn write_wavetable<W:Write>(writer:&mut W, frames:&[Vec<f32>], table_len:usize) -> io::Result<()> {
+    if frames.is_empty() || frames.iter().any(|frame| frame.len() != table_len) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("every wavetable frame must be {table_len} samples long")));
+    }
+
+    let samples:Vec<f32> = frames.concat();
+    let spec = WavSpec { channels:1, sample_rate:WAVETABLE_SAMPLE_RATE, bit_depth:BitDepth::Float32 };
+    wav::write_wav_with_chunks(writer, spec, &samples, &[(*b"clm ", clm_chunk(table_len))])
+}
@@ -0,0 +1,93 @@
+// Renders a wave to a wav file without opening a window or a sound card, handy for auditioning and diffing shapes in CI
+// With --wavetable the wave is written as a single cycle wavetable of that length instead of N seconds of audio
+// With --import the nodes are fitted to a single cycle wav (or a frame of a wavetable) instead of given with --nodes
+//
+// usage: render_wav <output.wav> [--nodes pos:amp,pos:amp,...] [--seconds N] [--sample-rate HZ] [--bits 16|24|32]
+//                                [--freq HZ] [--interpolation NAME] [--wavetable 256|2048]
+//                                [--import input.wav] [--frame N]
+
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+
+use wasm_waveshaper::audio::{BitDepth, InterpolationMode, SoundEngine};
+
+const USAGE:&str = "usage: render_wav <output.wav> [--nodes pos:amp,pos:amp,...] [--seconds N] [--sample-rate HZ] \
+                    [--bits 16|24|32] [--freq HZ] [--interpolation NAME] [--wavetable 256|2048] [--import input.wav] [--frame N]";
+
+// a single cycle saw, used when no nodes are given
+const DEFAULT_NODES:&str = "0.0:-1.0,0.999:1.0";
+
+fn parse_nodes(arg:&str) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+    arg.split(',')
+        .map(|pair| {
+            let (pos, amp) = pair.split_once(':').ok_or_else(|| format!("node '{pair}' should look like pos:amp"))?;
+            Ok((pos.trim().parse::<f32>()?, amp.trim().parse::<f32>()?))
+        })
+        .collect()
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let out_path = args.next().ok_or(USAGE)?;
+
+    let mut nodes = parse_nodes(DEFAULT_NODES)?;
+    let mut seconds = 2.0f32;
+    let mut sample_rate = 44100u32;
+    let mut bit_depth = BitDepth::Int16;
+    let mut frequency:Option<f32> = None;
+    let mut interpolation = InterpolationMode::Linear;
+    let mut wavetable_len:Option<usize> = None;
+    let mut import_path:Option<String> = None;
+    let mut import_frame = 0usize;
+
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("missing value for {flag}\n{USAGE}"))?;
+        match flag.as_str() {
+            "--nodes" => nodes = parse_nodes(&value)?,
+            "--seconds" => seconds = value.parse()?,
+            "--sample-rate" => sample_rate = value.parse()?,
+            "--bits" => bit_depth = BitDepth::from_bits(value.parse()?).ok_or("bit depth must be 16, 24 or 32")?,
+            "--freq" => frequency = Some(value.parse()?),
+            "--interpolation" => {
+                interpolation = *InterpolationMode::ALL.iter()
+                    .find(|mode| mode.name() == value.replace('-', " ") || mode.name() == value)
+                    .ok_or_else(|| format!("unknown interpolation mode '{value}'"))?;
+            },
+            "--wavetable" => wavetable_len = Some(value.parse()?),
+            "--import" => import_path = Some(value),
+            "--frame" => import_frame = value.parse()?,
+            _ => return Err(format!("unknown argument {flag}\n{USAGE}").into()),
+        }
+    }
+
+    let mut engine = SoundEngine::without_device();
+    match import_path {
+        Some(path) => {
+            let fitted = engine.import_wav(&std::fs::read(&path)?, import_frame)?;
+            println!("fitted {} nodes to {path}", fitted.len());
+        },
+        None => {
+            for (wave_pos, amplitude) in nodes {
+                engine.add_node(wave_pos, amplitude);
+            }
+        },
+    }
+    engine.set_interpolation_mode(interpolation);
+    if let Some(frequency) = frequency {
+        engine.set_frequency(frequency);
+    }
+
+    let mut writer = BufWriter::new(File::create(&out_path)?);
+    match wavetable_len {
+        Some(table_len) => {
+            engine.export_wavetable(&mut writer, table_len)?;
+            println!("wrote a {table_len} sample wavetable to {out_path}");
+        },
+        None => {
+            engine.render_to_wav(&mut writer, seconds, sample_rate, bit_depth)?;
+            println!("wrote {seconds}s at {sample_rate}Hz/{}bit to {out_path}", bit_depth.bits());
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+// Getting bytes in and out of the app looks different depending on where it runs: natively we can just read and
+// write files next to the executable, in the browser the best we can do is a download or a file picker
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{prelude::*, JsCast};
+
+// the bytes of a file, or the reason there are none
+pub type FileResult = Result<Vec<u8>, String>;
+
+// a file the user has been asked for that may not have arrived yet, the browser hands files over asynchronously
+// so this gets polled from the event loop until the result shows up
+pub struct PendingFile(Rc<RefCell<Option<FileResult>>>);
+
+impl PendingFile {
+    pub fn poll(&self) -> Option<FileResult> {
+        self.0.borrow_mut().take()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_file(file_name:&str, bytes:&[u8]) -> Result<(), Box<dyn Error>> {
+    std::fs::write(file_name, bytes)?;
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_file(file_name:&str, bytes:&[u8]) -> Result<(), Box<dyn Error>> {
+    // errors coming back from javascript are JsValues, which don't implement Error, so turn them into strings
+    let js_err = |err:wasm_bindgen::JsValue| format!("{:?}", err);
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts).map_err(js_err)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_err)?;
+
+    // clicking a temporary link with the download attribute set is how the browser is asked to save a blob
+    let document = web_sys::window().and_then(|win| win.document()).ok_or("no document to download from")?;
+    let anchor = document.create_element("a").map_err(js_err)?
+        .dyn_into::<web_sys::HtmlAnchorElement>().map_err(|_| "couldn't create a download link")?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    web_sys::Url::revoke_object_url(&url).map_err(js_err)?;
+    Ok(())
+}
+
+// keeps a bit of text around between runs, natively as a file next to the executable, in the browser in local storage
+// so it survives a page refresh
+#[cfg(not(target_arch = "wasm32"))]
+pub fn store_text(key:&str, text:&str) -> Result<(), Box<dyn Error>> {
+    std::fs::write(key, text)?;
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn store_text(key:&str, text:&str) -> Result<(), Box<dyn Error>> {
+    let storage = local_storage().ok_or("local storage isn't available")?;
+    storage.set_item(key, text).map_err(|err| format!("{:?}", err))?;
+    Ok(())
+}
+
+// text kept by store_text under the same key, if there is any
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_stored_text(key:&str) -> Option<String> {
+    std::fs::read_to_string(key).ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load_stored_text(key:&str) -> Option<String> {
+    local_storage()?.get_item(key).ok().flatten()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+// natively the file name is read straight away, accept is only used to filter the browser's file picker
+#[cfg(not(target_arch = "wasm32"))]
+pub fn request_file(file_name:&str, _accept:&str) -> PendingFile {
+    let result = std::fs::read(file_name).map_err(|err| format!("couldn't read {file_name}: {err}"));
+    PendingFile(Rc::new(RefCell::new(Some(result))))
+}
+
+// in the browser the user picks the file, the file name is ignored
+#[cfg(target_arch = "wasm32")]
+pub fn request_file(_file_name:&str, accept:&str) -> PendingFile {
+    let slot = Rc::new(RefCell::new(None));
+    let pending = PendingFile(slot.clone());
+
+    let input = match create_file_input(accept) {
+        Ok(input) => input,
+        Err(err) => {
+            *slot.borrow_mut() = Some(Err(err));
+            return pending
+        }
+    };
+
+    let input_handle = input.clone();
+    let on_change = Closure::<dyn FnMut()>::new(move || {
+        let slot = slot.clone();
+        match input_handle.files().and_then(|files| files.get(0)) {
+            None => *slot.borrow_mut() = Some(Err("no file was chosen".to_string())),
+            Some(file) => {
+                wasm_bindgen_futures::spawn_local(async move {
+                    let result = wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await
+                        .map(|buffer| js_sys::Uint8Array::new(&buffer).to_vec())
+                        .map_err(|err| format!("{:?}", err));
+                    *slot.borrow_mut() = Some(result);
+                });
+            }
+        }
+    });
+    input.set_onchange(Some(on_change.as_ref().unchecked_ref()));
+    // the browser calls this whenever the user picks a file, so it has to outlive this function
+    on_change.forget();
+    input.click();
+
+    pending
+}
+
+#[cfg(target_arch = "wasm32")]
+fn create_file_input(accept:&str) -> Result<web_sys::HtmlInputElement, String> {
+    let document = web_sys::window().and_then(|win| win.document()).ok_or("no document to pick a file from")?;
+    let input = document.create_element("input").map_err(|err| format!("{:?}", err))?
+        .dyn_into::<web_sys::HtmlInputElement>().map_err(|_| "couldn't create a file picker")?;
+    input.set_type("file");
+    input.set_accept(accept);
+    Ok(input)
+}
@@ -0,0 +1,185 @@
+// Edit log behind undo and redo
+// Every change to the shape is recorded as a command that knows how to apply itself to a node list and how to undo itself.
+// The commands only ever describe nodes, ShaperState applies them to the audio and the visual state together so the two
+// can't drift apart. Settings of the wave as a whole (interpolation mode, band limiting, pitch) are left out on purpose,
+// undoing an edit brings back the nodes as they were and plays them with whatever the settings are now.
+
+use std::collections::VecDeque;
+
+use crate::audio::{SegmentCurve, WaveNode};
+
+// oldest edits are forgotten past this many
+const MAX_HISTORY_LEN:usize = 256;
+
+#[derive(Clone, Debug)]
+pub enum EditCommand {
+    AddNode(WaveNode),
+    RemoveNode(WaveNode),
+    MoveNode { from:WaveNode, to:WaveNode },
+    // change of the curve of the segment that starts at the node at wave_pos
+    ChangeCurve { wave_pos:f32, from:SegmentCurve, to:SegmentCurve },
+    // anything that touches many nodes at once, like importing a wav or rebuilding the wave from the screen
+    ReplaceNodes { before:Vec<WaveNode>, after:Vec<WaveNode> },
+}
+
+impl EditCommand {
+    // the command that takes the node list back to how it was before this one
+    pub fn inverse(&self) -> EditCommand {
+        match self {
+            EditCommand::AddNode(node) => EditCommand::RemoveNode(*node),
+            EditCommand::RemoveNode(node) => EditCommand::AddNode(*node),
+            EditCommand::MoveNode { from, to } => EditCommand::MoveNode { from:*to, to:*from },
+            EditCommand::ChangeCurve { wave_pos, from, to } => EditCommand::ChangeCurve { wave_pos:*wave_pos, from:*to, to:*from },
+            EditCommand::ReplaceNodes { before, after } => EditCommand::ReplaceNodes { before:after.clone(), after:before.clone() },
+        }
+    }
+
+    // applies the command to a node list sorted by wave pos, nodes are found by their wave pos since those are unique
+    pub fn apply(&self, nodes:&mut Vec<WaveNode>) {
+        match self {
+            EditCommand::AddNode(node) => insert_sorted(nodes, *node),
+            EditCommand::RemoveNode(node) => nodes.retain(|probe| probe.wave_pos() != node.wave_pos()),
+            EditCommand::MoveNode { from, to } => {
+                nodes.retain(|probe| probe.wave_pos() != from.wave_pos());
+                insert_sorted(nodes, *to);
+            },
+            EditCommand::ChangeCurve { wave_pos, to, .. } => {
+                if let Some(node) = nodes.iter_mut().find(|probe| probe.wave_pos() == *wave_pos) {
+                    *node = WaveNode::new(node.wave_pos(), node.amplitude(), *to);
+                }
+            },
+            EditCommand::ReplaceNodes { after, .. } => *nodes = after.clone(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EditCommand::AddNode(_) => "add node",
+            EditCommand::RemoveNode(_) => "remove node",
+            EditCommand::MoveNode { .. } => "move node",
+            EditCommand::ChangeCurve { .. } => "change curve",
+            EditCommand::ReplaceNodes { .. } => "replace nodes",
+        }
+    }
+}
+
+fn insert_sorted(nodes:&mut Vec<WaveNode>, node:WaveNode) {
+    let index = nodes.partition_point(|probe| probe.wave_pos() < node.wave_pos());
+    nodes.insert(index, node);
+}
+
+#[derive(Default)]
+pub struct EditHistory {
+    // newest at the back, the oldest fall off the front
+    undo_stack:VecDeque<EditCommand>,
+    redo_stack:Vec<EditCommand>,
+}
+
+impl EditHistory {
+    // remembers an edit that has already been made, a new edit means the undone ones can't be redone anymore
+    pub fn record(&mut self, command:EditCommand) {
+        self.redo_stack.clear();
+        self.undo_stack.push_back(command);
+        if self.undo_stack.len() > MAX_HISTORY_LEN {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    // returns the command that undoes the latest edit, the caller has to apply it
+    pub fn undo(&mut self) -> Option<EditCommand> {
+        let command = self.undo_stack.pop_back()?;
+        let inverse = command.inverse();
+        self.redo_stack.push(command);
+        Some(inverse)
+    }
+
+    // returns the latest undone edit so it can be applied again
+    pub fn redo(&mut self) -> Option<EditCommand> {
+        let command = self.redo_stack.pop()?;
+        self.undo_stack.push_back(command.clone());
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(wave_pos:f32, amplitude:f32) -> WaveNode {
+        WaveNode::new(wave_pos, amplitude, SegmentCurve::Linear)
+    }
+
+    fn start() -> Vec<WaveNode> {
+        vec![node(0.0, 0.5), node(0.25, -0.5), node(0.5, 1.0)]
+    }
+
+    // records the command once it's been made, then checks undo takes the nodes back and redo brings the edit back
+    fn assert_undoes_and_redoes(command:EditCommand, after:Vec<WaveNode>) {
+        let mut nodes = start();
+        command.apply(&mut nodes);
+        assert_eq!(nodes, after, "{} made the wrong edit", command.name());
+
+        let mut history = EditHistory::default();
+        history.record(command.clone());
+        history.undo().unwrap().apply(&mut nodes);
+        assert_eq!(nodes, start(), "undoing {} didn't take it back", command.name());
+        history.redo().unwrap().apply(&mut nodes);
+        assert_eq!(nodes, after, "redoing {} didn't bring it back", command.name());
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn add_node() {
+        assert_undoes_and_redoes(EditCommand::AddNode(node(0.375, 0.25)),
+            vec![node(0.0, 0.5), node(0.25, -0.5), node(0.375, 0.25), node(0.5, 1.0)]);
+    }
+
+    #[test]
+    fn remove_node() {
+        assert_undoes_and_redoes(EditCommand::RemoveNode(node(0.25, -0.5)), vec![node(0.0, 0.5), node(0.5, 1.0)]);
+    }
+
+    #[test]
+    fn move_node() {
+        // past its neighbour, so it has to be put back in order both ways
+        assert_undoes_and_redoes(EditCommand::MoveNode { from:node(0.25, -0.5), to:node(0.75, 0.0) },
+            vec![node(0.0, 0.5), node(0.5, 1.0), node(0.75, 0.0)]);
+    }
+
+    #[test]
+    fn change_curve() {
+        let curve = SegmentCurve::Exponential(4.0);
+        assert_undoes_and_redoes(EditCommand::ChangeCurve { wave_pos:0.25, from:SegmentCurve::Linear, to:curve },
+            vec![node(0.0, 0.5), WaveNode::new(0.25, -0.5, curve), node(0.5, 1.0)]);
+    }
+
+    #[test]
+    fn replace_nodes() {
+        let after = vec![node(0.125, 0.0), node(0.875, -1.0)];
+        assert_undoes_and_redoes(EditCommand::ReplaceNodes { before:start(), after:after.clone() }, after);
+    }
+
+    #[test]
+    fn a_new_edit_forgets_the_undone_ones() {
+        let mut history = EditHistory::default();
+        history.record(EditCommand::AddNode(node(0.75, 0.0)));
+        history.undo();
+        history.record(EditCommand::AddNode(node(0.875, 0.0)));
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn forgets_the_oldest_edits_past_the_limit() {
+        let mut history = EditHistory::default();
+        for index in 0..MAX_HISTORY_LEN + 10 {
+            history.record(EditCommand::AddNode(node(index as f32 / 1000.0, 0.0)));
+        }
+        let undone = std::iter::from_fn(|| history.undo()).collect::<Vec<_>>();
+        assert_eq!(undone.len(), MAX_HISTORY_LEN);
+        // the last edit that can be undone is the oldest one still remembered
+        match undone.last() {
+            Some(EditCommand::RemoveNode(node)) => assert_eq!(node.wave_pos(), 10.0 / 1000.0),
+            other => panic!("expected the undo of an added node, got {other:?}"),
+        }
+    }
+}
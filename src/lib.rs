@@ -1,5 +1,12 @@
 mod rendering;
-mod audio;
+mod file_io;
+mod history;
+mod session;
+mod share_link;
+mod midi;
+pub mod audio;
+
+use std::f32::consts::PI;
 
 use winit::{
     event::*,
@@ -7,14 +14,86 @@ use winit::{
     window::{WindowBuilder}, dpi::Position,
 };
 
+use history::EditCommand;
 use web_sys;
 use wasm_bindgen::prelude::*;
 
-// TODO: converts an entire exported visual state to a format that the audio renderer can recognize as a wave shape
+// converts an entire exported visual state to a format that the audio renderer can recognize as a wave shape
 // based on the anchors in the visual state and whether the anchors are in scope
 fn wave_shape_from_visual_state(visual_state:rendering::VisualState) -> Vec<audio::WaveNode> {
-    // TODO: use filter pattern here in the spirit of functional programming
-    return vec![]
+    visual_state.anchors_in_scope()
+        .map(|(world_loc, curve)| node_at_world_location(world_loc, audio::SegmentCurve::from_gpu_params(curve)))
+        .collect()
+}
+
+// one cycle of the wave spans -1 to 1 in world x and the amplitude is the world y, so the view can be zoomed and
+// moved around without changing the sound
+fn node_at_world_location(world_loc:[f32;4], curve:audio::SegmentCurve) -> audio::WaveNode {
+    audio::WaveNode::new((world_loc[0] + 1.0) / 2.0, world_loc[1], curve)
+}
+
+fn world_location_of_node(node:&audio::WaveNode) -> [f32;4] {
+    [node.wave_pos() * 2.0 - 1.0, node.amplitude(), 0.0, 1.0]
+}
+
+// anchors can be dragged right up to the end of the cycle but not onto it, that's where the next cycle starts
+const MAX_DRAG_WORLD_X:f32 = 1.0 - f32::EPSILON;
+
+// keeps a world location inside one cycle and the amplitude range, the view can be zoomed and moved out past both
+fn clamp_to_cycle([x, y, z, w]:[f32;4]) -> [f32;4] {
+    [x.clamp(-1.0, MAX_DRAG_WORLD_X), y.clamp(-1.0, 1.0), z, w]
+}
+
+// each press of a zoom key zooms in or out by this factor
+const ZOOM_STEP:f32 = 1.25;
+// each press of an arrow key moves the view by a tenth of its width or height, clip space is 2 across
+const PAN_STEP:f32 = 0.2;
+
+// grid divisions are halved and doubled within these bounds
+const MIN_GRID_DIVISIONS:u32 = 1;
+const MAX_GRID_DIVISIONS:u32 = 64;
+
+// file read natively when importing, in the browser the user picks the file instead
+const IMPORT_FILE_NAME:&str = "import.wav";
+// sessions are kept under this name natively and in the browser's local storage, and downloaded with it
+const SESSION_FILE_NAME:&str = "session.wvs";
+
+// how far one notch of the mouse wheel moves the pitch, holding shift moves by semitones and ctrl by octaves
+const CENTS_PER_WHEEL_STEP:f32 = 10.0;
+const CENTS_PER_SEMITONE:f32 = 100.0;
+const CENTS_PER_OCTAVE:f32 = 1200.0;
+// trackpads and some browsers scroll in pixels instead of notches, this many pixels counts as one notch
+const PIXELS_PER_WHEEL_STEP:f32 = 100.0;
+
+// how MIDI controllers are routed: the pitch wheel bends by up to 2 semitones either way, the mod wheel brings in vibrato
+const PITCH_BEND_RANGE_CENTS:f32 = 200.0;
+const MAX_VIBRATO_CENTS:f32 = 50.0;
+const MOD_WHEEL_CONTROLLER:u8 = 1;
+// "all sound off" and "all notes off", sent by panic buttons and by some keyboards when they're switched off
+const ALL_SOUND_OFF_CONTROLLER:u8 = 120;
+const ALL_NOTES_OFF_CONTROLLER:u8 = 123;
+
+// each press of an envelope key scales a stage's time by this much, and moves the sustain by a step
+const ENVELOPE_TIME_STEP:f32 = 1.5;
+const SUSTAIN_STEP:f32 = 0.1;
+const MIN_ENVELOPE_TIME:f32 = 0.001;
+const MAX_ENVELOPE_TIME:f32 = 10.0;
+
+// on the modulator layer a notch of the mouse wheel stretches (up) or squeezes (down) the modulator by this much
+// instead of changing the pitch
+const MODULATOR_TIME_STEP:f32 = 1.25;
+
+// each press of a modulation key moves the depth of the selected route by a step, or scales an LFO's rate
+const MOD_DEPTH_STEP:f32 = 0.1;
+const LFO_RATE_STEP:f32 = 1.5;
+
+// what goes with the layer the editor isn't showing, put back when the editor switches to it
+#[derive(Default)]
+struct ParkedLayer {
+    // anchors and view, None if the layer hasn't been shown yet (or its shape was replaced from somewhere else while
+    // it was parked), its anchors are then rebuilt from its nodes
+    visual_state:Option<rendering::VisualState>,
+    history:history::EditHistory,
 }
 
 //TODO: shaper state is basically the UI layer. Why does it "own" instances of AudioState and RenderState?
@@ -22,15 +101,554 @@ fn wave_shape_from_visual_state(visual_state:rendering::VisualState) -> Vec<audi
 pub struct ShaperState {
     render_state:rendering::State,
     sound_engine:audio::SoundEngine,
+    // wav file that has been asked for but hasn't arrived yet
+    pending_import:Option<file_io::PendingFile>,
+    // same for a session file
+    pending_session:Option<file_io::PendingFile>,
+    // modifier keys currently held down
+    modifiers:ModifiersState,
+    // index of the anchor being dragged by the mouse, if one is
+    dragged_anchor:Option<usize>,
+    // node the dragged anchor was picked up from, so the whole drag can be undone as one move
+    drag_origin:Option<audio::WaveNode>,
+    // edits that can be undone and redone
+    history:history::EditHistory,
+    // keyboard the wave can be played from
+    midi:midi::MidiSource,
+    // harmonics being edited as sliders, None while editing anchors
+    harmonics:Option<audio::Harmonics>,
+    // nodes from before a slider was grabbed, so the whole drag can be undone as one edit
+    harmonics_origin:Option<Vec<audio::WaveNode>>,
+    // whether the trace of what the engine played is drawn over the wave
+    show_scope:bool,
+    // the wave or the modulator, whichever isn't being edited
+    parked_layer:ParkedLayer,
+    // slot of the modulation matrix the modulation keys change
+    selected_route:usize,
 }
 impl ShaperState {
     fn new(render_state:rendering::State, sound_engine:audio::SoundEngine) -> ShaperState {
         ShaperState {
             render_state,
             sound_engine,
+            pending_import:None,
+            pending_session:None,
+            modifiers:ModifiersState::empty(),
+            dragged_anchor:None,
+            drag_origin:None,
+            history:history::EditHistory::default(),
+            midi:midi::MidiSource::open(),
+            harmonics:None,
+            harmonics_origin:None,
+            show_scope:true,
+            parked_layer:ParkedLayer::default(),
+            selected_route:0,
+        }
+    }
+
+    // size of one wheel notch in cents, depends on which modifier is held
+    fn wheel_step_cents(&self) -> f32 {
+        if self.modifiers.ctrl() {
+            CENTS_PER_OCTAVE
+        } else if self.modifiers.shift() {
+            CENTS_PER_SEMITONE
+        } else {
+            CENTS_PER_WHEEL_STEP
+        }
+    }
+
+    fn update_pitch_readout(&self) {
+        match self.sound_engine.playing_pitch() {
+            Some(pitch) => self.render_state.set_readout(&format!("{} ({:.2} Hz)", pitch.name(), pitch.frequency())),
+            None => self.render_state.set_readout("no wave"),
+        }
+    }
+
+    // replaces the anchors with ones matching the nodes
+    fn show_nodes(&mut self, nodes:&[audio::WaveNode]) {
+        let anchors:Vec<([f32;4], [f32;4])> = nodes.iter()
+            .map(|node| (world_location_of_node(node), node.curve().gpu_params()))
+            .collect();
+        self.render_state.replace_anchors_from_world_locations(&anchors);
+    }
+
+    // the card fills in between anchors the way the wave does, a wave that was removed and drawn again starts over
+    // from the default mode
+    fn show_interpolation(&mut self) {
+        let mode = self.sound_engine.interpolation_mode().unwrap_or(audio::InterpolationMode::Linear);
+        self.render_state.set_interpolation(mode.gpu_kind());
+    }
+
+    // applies an edit to the node list and rebuilds both the wave and the anchors from the result
+    fn apply_edit(&mut self, command:&EditCommand) {
+        let mut nodes = self.sound_engine.nodes();
+        command.apply(&mut nodes);
+        self.show_nodes(&nodes);
+        self.sound_engine.replace_nodes(nodes);
+        self.show_interpolation();
+        self.sync_harmonics();
+        self.update_pitch_readout();
+        self.update_share_link();
+    }
+
+    // remembers an edit that has already been made to both states
+    fn record_edit(&mut self, command:EditCommand) {
+        self.history.record(command);
+        self.show_interpolation();
+        self.update_share_link();
+    }
+
+    // keeps the page URL pointing at the current sound so it can be copied and shared
+    fn update_share_link(&self) {
+        let shape = share_link::SharedShape {
+            nodes:self.sound_engine.layer_nodes(audio::Layer::Wave),
+            interpolation:self.sound_engine.layer_interpolation_mode(audio::Layer::Wave).unwrap_or(audio::InterpolationMode::Linear),
+            frequency:self.sound_engine.pitch().map(|pitch| pitch.frequency()),
+        };
+        share_link::write_to_page_url(&share_link::encode(&shape));
+    }
+
+    // loads the shape in the page URL if it was opened from a link, returns whether it did
+    fn restore_share_link(&mut self) -> bool {
+        let Some(fragment) = share_link::read_from_page_url() else {
+            return false
+        };
+        match share_link::decode(&fragment) {
+            Ok(shape) => {
+                log::warn!("restored {} nodes from the page url", shape.nodes.len());
+                let session = session::Session {
+                    nodes:shape.nodes,
+                    interpolation:shape.interpolation,
+                    frequency:shape.frequency,
+                    ..self.session()
+                };
+                self.restore_session(session);
+                true
+            },
+            Err(err) => {
+                log::warn!("couldn't read the shape in the page url: {err}");
+                false
+            },
+        }
+    }
+
+    fn undo(&mut self) {
+        self.finish_drag();
+        self.finish_harmonic_drag();
+        match self.history.undo() {
+            Some(command) => {
+                log::warn!("undo {}", command.name());
+                self.apply_edit(&command);
+            },
+            None => log::warn!("nothing to undo"),
+        }
+    }
+
+    fn redo(&mut self) {
+        self.finish_drag();
+        self.finish_harmonic_drag();
+        match self.history.redo() {
+            Some(command) => {
+                log::warn!("redo {}", command.name());
+                self.apply_edit(&command);
+            },
+            None => log::warn!("nothing to redo"),
+        }
+    }
+
+    // lets go of the dragged anchor, recording where it went as a single move
+    fn finish_drag(&mut self) {
+        let (Some(index), Some(from)) = (self.dragged_anchor.take(), self.drag_origin.take()) else {
+            return
+        };
+        if let Some(to) = self.sound_engine.nodes().get(index).copied() {
+            if to != from {
+                self.record_edit(EditCommand::MoveNode { from, to });
+            }
+        }
+        self.sound_engine.print_node_list();
+    }
+
+    // removes the anchor under the cursor and its node, if there is one
+    fn remove_anchor_at_cursor(&mut self) {
+        // the anchor being dragged may shift or be the one removed, so let go of it first
+        self.finish_drag();
+        let Some(index) = self.render_state.anchor_at_clip_location(self.render_state.get_cursor_clip_location()) else {
+            return
+        };
+        if self.render_state.remove_anchor(index) {
+            match self.sound_engine.remove_node(index) {
+                Some(node) => self.record_edit(EditCommand::RemoveNode(node)),
+                None => log::warn!("Error: anchor {index} had no node to remove"),
+            }
+            log::warn!("removed node at index: {index}");
+            self.sound_engine.print_node_list();
+            self.update_pitch_readout();
+        }
+    }
+
+    // world location the cursor is placing or dragging an anchor to, on the nearest grid intersection while alt is held
+    fn cursor_placement_location(&self) -> [f32;4] {
+        let cursor_loc = self.render_state.get_cursor_world_location();
+        match self.modifiers.alt() {
+            true => self.render_state.snap_world_location(cursor_loc),
+            false => cursor_loc,
+        }
+    }
+
+    // halves (negative) or doubles (positive) the number of grid columns (x) and rows (y), zero leaves them be
+    fn rescale_grid(&mut self, x_change:i32, y_change:i32) {
+        let rescale = |divisions:u32, change:i32| match change {
+            1.. => (divisions * 2).min(MAX_GRID_DIVISIONS),
+            0 => divisions,
+            _ => (divisions / 2).max(MIN_GRID_DIVISIONS),
+        };
+        let grid = self.render_state.grid();
+        let grid = rendering::Grid { x_divisions:rescale(grid.x_divisions, x_change), y_steps:rescale(grid.y_steps, y_change), ..grid };
+        self.render_state.set_grid(grid);
+        log::warn!("grid is now {} x {}", grid.x_divisions, grid.y_steps);
+    }
+
+    // moves the dragged anchor and its node to the cursor, so the sound follows the drag
+    fn drag_anchor_to_cursor(&mut self, anchor_index:usize) {
+        let world_loc = clamp_to_cycle(self.cursor_placement_location());
+        let node = node_at_world_location(world_loc, audio::SegmentCurve::Linear);
+        // the node moves first and the anchor only follows it, so a move the wave turns down leaves both where they were
+        let Some(new_index) = self.sound_engine.move_node(anchor_index, node.wave_pos(), node.amplitude()) else {
+            return
+        };
+        if self.render_state.move_anchor_to_world_location(anchor_index, world_loc) != Some(new_index) {
+            // the anchors are redrawn from the nodes, otherwise every later edit would land on the wrong node
+            log::warn!("anchor {anchor_index} and its node ended up out of step, redrawing the anchors");
+            let nodes = self.sound_engine.nodes();
+            self.show_nodes(&nodes);
+        }
+        self.dragged_anchor = Some(new_index);
+    }
+
+    // switches between drawing anchors and editing the harmonics of the shape as sliders
+    fn toggle_harmonic_mode(&mut self) {
+        self.finish_drag();
+        self.finish_harmonic_drag();
+        self.harmonics = match self.harmonics {
+            Some(_) => None,
+            None => Some(self.sound_engine.harmonics().unwrap_or_default()),
+        };
+        self.show_harmonic_sliders();
+        log::warn!("harmonic editing {}", if self.harmonics.is_some() { "on" } else { "off" });
+    }
+
+    // the shape was changed some other way than the sliders, so they start over from the new shape
+    fn sync_harmonics(&mut self) {
+        if self.harmonics.is_some() {
+            self.harmonics = Some(self.sound_engine.harmonics().unwrap_or_default());
+            self.show_harmonic_sliders();
+        }
+    }
+
+    fn show_harmonic_sliders(&mut self) {
+        let sliders:Vec<[f32;2]> = match &self.harmonics {
+            Some(harmonics) => (0..audio::NUM_HARMONICS)
+                .map(|index| [harmonics.amplitude(index), (harmonics.phase(index) + PI) / (2.0 * PI)])
+                .collect(),
+            None => vec![],
+        };
+        self.render_state.set_harmonic_sliders(&sliders);
+    }
+
+    // sets the slider under the cursor to the cursor's height, the phase while shift is held, and rebuilds the wave
+    fn drag_harmonic_slider_to_cursor(&mut self) {
+        let cursor_loc = self.render_state.get_cursor_clip_location();
+        let Some((index, value)) = self.render_state.harmonic_slider_at_clip_location(cursor_loc, audio::NUM_HARMONICS) else {
+            return
+        };
+        let Some(harmonics) = self.harmonics.as_mut() else {
+            return
+        };
+        match self.modifiers.shift() {
+            true => harmonics.set_phase(index, value * 2.0 * PI - PI),
+            false => harmonics.set_amplitude(index, value),
+        }
+        let nodes = harmonics.to_nodes();
+        self.show_nodes(&nodes);
+        self.sound_engine.replace_nodes(nodes);
+        self.show_harmonic_sliders();
+        self.update_pitch_readout();
+    }
+
+    // lets go of the sliders, recording the whole drag as one edit
+    fn finish_harmonic_drag(&mut self) {
+        if let Some(before) = self.harmonics_origin.take() {
+            let after = self.sound_engine.nodes();
+            if after != before {
+                self.record_edit(EditCommand::ReplaceNodes { before, after });
+            }
+        }
+    }
+
+    // lengthens (or with shift shortens) one stage of the envelope, 1 to 4 are attack, decay, sustain and release
+    fn adjust_envelope(&mut self, key:VirtualKeyCode) {
+        let mut adsr = self.sound_engine.adsr();
+        let (time_factor, sustain_step) = match self.modifiers.shift() {
+            true => (1.0 / ENVELOPE_TIME_STEP, -SUSTAIN_STEP),
+            false => (ENVELOPE_TIME_STEP, SUSTAIN_STEP),
+        };
+        let scale_time = |time:f32| (time * time_factor).clamp(MIN_ENVELOPE_TIME, MAX_ENVELOPE_TIME);
+        match key {
+            VirtualKeyCode::Key1 => adsr.attack = scale_time(adsr.attack),
+            VirtualKeyCode::Key2 => adsr.decay = scale_time(adsr.decay),
+            VirtualKeyCode::Key3 => adsr.sustain = (adsr.sustain + sustain_step).clamp(0.0, 1.0),
+            VirtualKeyCode::Key4 => adsr.release = scale_time(adsr.release),
+            _ => return,
+        }
+        self.sound_engine.set_adsr(adsr);
+        log::warn!("envelope is now: attack {:.3}s, decay {:.3}s, sustain {:.1}, release {:.3}s", adsr.attack, adsr.decay, adsr.sustain, adsr.release);
+    }
+
+    // switches the editor between the wave and the modulator, each keeps its own anchors, view and undo history
+    fn switch_layer(&mut self) {
+        self.finish_drag();
+        self.finish_harmonic_drag();
+        let layer = self.sound_engine.edit_layer().other();
+        let leaving = ParkedLayer {
+            visual_state:Some(self.render_state.export_visual_state()),
+            history:std::mem::take(&mut self.history),
+        };
+        let ParkedLayer { visual_state, history } = std::mem::replace(&mut self.parked_layer, leaving);
+        self.sound_engine.set_edit_layer(layer);
+        self.history = history;
+        match visual_state {
+            Some(visual_state) => self.render_state.import_visual_state(visual_state),
+            None => {
+                let nodes = self.sound_engine.nodes();
+                self.show_nodes(&nodes);
+            },
+        }
+        self.sync_harmonics();
+        self.show_interpolation();
+        log::warn!("editing the {} now", layer.name());
+    }
+
+    // stretches or squeezes how long one pass through the modulator takes
+    fn scale_modulator_time(&mut self, wheel_steps:f32) {
+        let settings = self.sound_engine.modulator_settings();
+        let seconds = settings.seconds * MODULATOR_TIME_STEP.powf(wheel_steps);
+        self.sound_engine.set_modulator_settings(audio::ModulatorSettings { seconds, ..settings });
+        log::warn!("modulator now takes {:.2}s", self.sound_engine.modulator_settings().seconds);
+    }
+
+    // changes the route in the selected slot of the modulation matrix
+    fn update_selected_route(&mut self, update:impl FnOnce(audio::ModRoute) -> audio::ModRoute) {
+        let mut mod_matrix = self.sound_engine.mod_matrix();
+        let route = &mut mod_matrix.routes[self.selected_route];
+        *route = update(*route);
+        self.sound_engine.set_mod_matrix(mod_matrix);
+        self.log_selected_route();
+    }
+
+    fn log_selected_route(&self) {
+        let route = self.sound_engine.mod_matrix().routes[self.selected_route];
+        log::warn!("route {}: {} to {} by {:.1}", self.selected_route + 1, route.source.name(), route.target.name(), route.depth);
+    }
+
+    // the node a route to a node targets, the one under the cursor if the wave's anchors are showing
+    fn route_node_index(&self) -> usize {
+        match self.sound_engine.edit_layer() {
+            audio::Layer::Wave => self.render_state.anchor_at_clip_location(self.render_state.get_cursor_clip_location()).unwrap_or(0),
+            audio::Layer::Modulator => 0,
+        }
+    }
+
+    // 7 and 8 speed up (or with shift slow down) the first and second LFO, 9 and 0 change their shapes
+    fn adjust_lfo(&mut self, key:VirtualKeyCode) {
+        let mut mod_matrix = self.sound_engine.mod_matrix();
+        let rate_factor = match self.modifiers.shift() {
+            true => 1.0 / LFO_RATE_STEP,
+            false => LFO_RATE_STEP,
+        };
+        let (index, change_shape) = match key {
+            VirtualKeyCode::Key7 => (0, false),
+            VirtualKeyCode::Key8 => (1, false),
+            VirtualKeyCode::Key9 => (0, true),
+            VirtualKeyCode::Key0 => (1, true),
+            _ => return,
+        };
+        let lfo = &mut mod_matrix.lfos[index];
+        match change_shape {
+            true => lfo.shape = lfo.shape.next(),
+            false => lfo.rate = (lfo.rate * rate_factor).clamp(audio::MIN_LFO_RATE, audio::MAX_LFO_RATE),
+        }
+        log::warn!("lfo {} is now: {} at {:.2} Hz", index + 1, lfo.shape.name(), lfo.rate);
+        self.sound_engine.set_mod_matrix(mod_matrix);
+    }
+
+    // snapshot of everything a saved session needs
+    fn session(&self) -> session::Session {
+        // the view saved is the wave's, even while the modulator is being edited
+        let shown = self.render_state.export_visual_state();
+        let visual_state = match (self.sound_engine.edit_layer(), &self.parked_layer.visual_state) {
+            (audio::Layer::Modulator, Some(parked)) => parked,
+            _ => &shown,
+        };
+        session::Session {
+            nodes:self.sound_engine.layer_nodes(audio::Layer::Wave),
+            interpolation:self.sound_engine.layer_interpolation_mode(audio::Layer::Wave).unwrap_or(audio::InterpolationMode::Linear),
+            band_limited:self.sound_engine.layer_band_limited(audio::Layer::Wave).unwrap_or(true),
+            frequency:self.sound_engine.pitch().map(|pitch| pitch.frequency()),
+            world_scale:visual_state.world_scale(),
+            view_center:visual_state.view_center(),
+            max_voices:self.sound_engine.max_voices(),
+            steal_policy:self.sound_engine.steal_policy(),
+            adsr:self.sound_engine.adsr(),
+            modulator_nodes:self.sound_engine.layer_nodes(audio::Layer::Modulator),
+            modulator_interpolation:self.sound_engine.layer_interpolation_mode(audio::Layer::Modulator).unwrap_or(audio::InterpolationMode::Linear),
+            modulator:self.sound_engine.modulator_settings(),
+            mod_matrix:self.sound_engine.mod_matrix(),
+        }
+    }
+
+    // puts the shape, sound and view back the way a session describes them
+    fn restore_session(&mut self, session:session::Session) {
+        self.finish_drag();
+        // the session is shown on the wave layer, the modulator's anchors and history no longer match its shape
+        if self.sound_engine.edit_layer() == audio::Layer::Modulator {
+            self.switch_layer();
+        }
+        self.parked_layer = ParkedLayer::default();
+        self.sound_engine.replace_layer_nodes(audio::Layer::Modulator, session.modulator_nodes);
+        self.sound_engine.set_layer_interpolation_mode(audio::Layer::Modulator, session.modulator_interpolation);
+        self.sound_engine.set_modulator_settings(session.modulator);
+        self.sound_engine.set_mod_matrix(session.mod_matrix);
+        self.render_state.update_view(session.world_scale, session.view_center);
+        // the anchors are drawn from what the wave made of the nodes, in its order
+        self.sound_engine.replace_nodes(session.nodes);
+        let nodes = self.sound_engine.nodes();
+        self.show_nodes(&nodes);
+        self.sound_engine.set_layer_interpolation_mode(audio::Layer::Wave, session.interpolation);
+        self.sound_engine.set_layer_band_limited(audio::Layer::Wave, session.band_limited);
+        self.sound_engine.set_max_voices(session.max_voices);
+        self.sound_engine.set_steal_policy(session.steal_policy);
+        self.sound_engine.set_adsr(session.adsr);
+        if let Some(frequency) = session.frequency {
+            self.sound_engine.set_frequency(frequency);
+        }
+        self.show_interpolation();
+        self.sync_harmonics();
+        self.update_pitch_readout();
+        self.update_share_link();
+    }
+
+    // picks up where the last run left off, if a session was saved
+    fn restore_stored_session(&mut self) {
+        let Some(text) = file_io::load_stored_text(SESSION_FILE_NAME) else {
+            return
+        };
+        match session::Session::from_text(&text) {
+            Ok(session) => {
+                log::warn!("restored a session with {} nodes", session.nodes.len());
+                self.restore_session(session);
+            },
+            Err(err) => log::warn!("couldn't restore the saved session: {err}"),
+        }
+    }
+
+    // keeps the session for the next run, and also downloads it as a file that can be shared or loaded later
+    fn save_session(&self, download:bool) {
+        let text = self.session().to_text();
+        match file_io::store_text(SESSION_FILE_NAME, &text) {
+            Ok(()) => log::warn!("session saved"),
+            Err(err) => log::warn!("couldn't save session: {err}"),
+        }
+        if download {
+            if let Err(err) = file_io::save_file(SESSION_FILE_NAME, text.as_bytes()) {
+                log::warn!("couldn't download session: {err}");
+            }
+        }
+    }
+
+    // plays whatever has come in from the keyboard since the last event
+    fn poll_midi(&mut self) {
+        for message in self.midi.poll() {
+            let pitch = match message {
+                midi::MidiMessage::NoteOn { note, velocity, .. } => self.sound_engine.note_on(note, velocity),
+                midi::MidiMessage::NoteOff { note, .. } => self.sound_engine.note_off(note),
+                midi::MidiMessage::PitchBend { value, .. } => {
+                    self.sound_engine.set_pitch_bend(value as f32 / 8192.0 * PITCH_BEND_RANGE_CENTS)
+                },
+                midi::MidiMessage::ControlChange { controller:MOD_WHEEL_CONTROLLER, value, .. } => {
+                    self.sound_engine.set_vibrato_depth(value as f32 / 127.0 * MAX_VIBRATO_CENTS);
+                    None
+                },
+                midi::MidiMessage::ControlChange { controller:ALL_SOUND_OFF_CONTROLLER | ALL_NOTES_OFF_CONTROLLER, .. } => {
+                    self.sound_engine.all_notes_off();
+                    None
+                },
+                midi::MidiMessage::ControlChange { .. } => None,
+            };
+            // playing isn't an edit, so only the readout follows the keyboard
+            if pitch.is_some() {
+                self.update_pitch_readout();
+            }
+        }
+    }
+
+    // once a requested session file shows up, load it, as one edit that can be undone
+    fn poll_pending_session(&mut self) {
+        let Some(result) = self.pending_session.as_ref().and_then(|pending| pending.poll()) else {
+            return
+        };
+        self.pending_session = None;
+
+        let loaded = result
+            .and_then(|bytes| String::from_utf8(bytes).map_err(|_| "session file isn't text".to_string()))
+            .and_then(|text| session::Session::from_text(&text));
+        match loaded {
+            Ok(session) => {
+                let before = self.sound_engine.layer_nodes(audio::Layer::Wave);
+                self.restore_session(session);
+                let after = self.sound_engine.layer_nodes(audio::Layer::Wave);
+                self.record_edit(EditCommand::ReplaceNodes { before, after });
+                log::warn!("loaded a session with {} nodes", self.sound_engine.nodes().len());
+            },
+            Err(err) => log::warn!("couldn't load session: {err}"),
+        }
+    }
+
+    // once a requested wav file shows up, fit nodes to it and show them as anchors
+    fn poll_pending_import(&mut self) {
+        let Some(result) = self.pending_import.as_ref().and_then(|pending| pending.poll()) else {
+            return
+        };
+        self.pending_import = None;
+
+        let before = self.sound_engine.nodes();
+        match result.map_err(|err| err.into()).and_then(|bytes| self.sound_engine.import_wav(&bytes, 0)) {
+            Ok(nodes) => {
+                self.show_nodes(&nodes);
+                self.sync_harmonics();
+                log::warn!("imported {} nodes", nodes.len());
+                self.record_edit(EditCommand::ReplaceNodes { before, after:nodes });
+            },
+            Err(err) => log::warn!("couldn't import wav: {err}"),
+        }
+    }
+
+    // copies the latest output into the scope trace, done once per frame right before it is drawn
+    fn refresh_scope(&mut self) {
+        match self.show_scope {
+            true => self.render_state.set_scope_samples(&self.sound_engine.scope_samples()),
+            false => self.render_state.set_scope_samples(&[]),
         }
     }
 
+    // same for the spectrum panel
+    fn refresh_spectrum(&mut self) {
+        let as_points = |bins:Vec<audio::SpectrumBin>| bins.iter().map(|bin| [bin.frequency, bin.level_db]).collect::<Vec<_>>();
+        let cycle_spectrum = as_points(self.sound_engine.cycle_spectrum().unwrap_or_default());
+        let output_spectrum = as_points(self.sound_engine.output_spectrum());
+        self.render_state.set_spectra(&cycle_spectrum, &output_spectrum);
+    }
+
     // TODO: holy shit this is so nested
     // handles interaction events that affect both the audio and visual states of the wave shaper
     fn handle_shaper_events(&mut self, event:&Event<()>, control_flow:&mut ControlFlow) {
@@ -39,43 +657,265 @@ impl ShaperState {
                 match event {
                     WindowEvent::MouseInput { state, button,.. } => {
                         match (button, state) {
+                            // the spectrum panel is only for looking at
+                            (MouseButton::Left, ElementState::Pressed) if !self.render_state.cursor_in_editor() => {},
+                            // in harmonic mode the editor is all sliders
+                            (MouseButton::Left, ElementState::Pressed) if self.harmonics.is_some() => {
+                                self.harmonics_origin = Some(self.sound_engine.nodes());
+                                self.drag_harmonic_slider_to_cursor();
+                            },
                             (MouseButton::Left, ElementState::Pressed) => {
-                                let new_node_loc = self.render_state.get_cursor_clip_location();
+                                let cursor_loc = self.render_state.get_cursor_clip_location();
+                                let new_node_loc = clamp_to_cycle(self.cursor_placement_location());
+                                // pressing on an existing anchor picks it up to be dragged
+                                if let Some(index) = self.render_state.anchor_at_clip_location(cursor_loc) {
+                                    self.dragged_anchor = Some(index);
+                                    self.drag_origin = self.sound_engine.nodes().get(index).copied();
+                                }
                                 // if the visual state successfully added a new anchor, add a new anchor to the sound state
-                                if self.render_state.add_circle_at_clip_location(new_node_loc) {
-                                    self.sound_engine.add_node( (new_node_loc[0] + 1.0) / 2.0, new_node_loc[1]);
+                                else if self.render_state.add_circle_at_world_location(new_node_loc) {
+                                    let new_node = node_at_world_location(new_node_loc, audio::SegmentCurve::Linear);
+                                    self.sound_engine.add_node(new_node.wave_pos(), new_node.amplitude());
+                                    self.record_edit(EditCommand::AddNode(new_node));
                                     self.sound_engine.print_node_list();
+                                    self.update_pitch_readout();
                                 }
                             },
+                            (MouseButton::Right, ElementState::Pressed) => {
+                                self.remove_anchor_at_cursor();
+                            },
+                            (MouseButton::Left, ElementState::Released) => {
+                                self.finish_drag();
+                                self.finish_harmonic_drag();
+                            },
                             _ => {}
                         }
                     },
-                    WindowEvent::MouseWheel { device_id, delta, phase, modifiers } => { 
-                        self.sound_engine.apply_delta_to_frequency(match delta {
-                            MouseScrollDelta::LineDelta(x, y) => {
-                                y * 0.001 //TODO: remove magic number
-                            },
-                            MouseScrollDelta::PixelDelta(pos) => {
-                                pos.y as f32 * 0.001 //TODO: remove magic number
-                            },
-                        });
+                    WindowEvent::CursorMoved { .. } => {
+                        if let Some(index) = self.dragged_anchor {
+                            self.drag_anchor_to_cursor(index);
+                        }
+                        if self.harmonics_origin.is_some() {
+                            self.drag_harmonic_slider_to_cursor();
+                        }
+                    },
+                    WindowEvent::ModifiersChanged(new_modifiers) => {
+                        self.modifiers = *new_modifiers;
+                    },
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        let wheel_steps = match delta {
+                            MouseScrollDelta::LineDelta(_, y) => *y,
+                            MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / PIXELS_PER_WHEEL_STEP,
+                        };
+                        if self.sound_engine.edit_layer() == audio::Layer::Modulator {
+                            self.scale_modulator_time(wheel_steps);
+                        }
+                        else if self.sound_engine.apply_cents_to_pitch(wheel_steps * self.wheel_step_cents()).is_some() {
+                            self.update_pitch_readout();
+                            self.update_share_link();
+                        }
                     },
                     WindowEvent::KeyboardInput { device_id, input, is_synthetic } => {
                         // KEYBOARD INPUT SECTION
                         match (input.virtual_keycode, input.state) {
-                            (Some(VirtualKeyCode::S), ElementState::Pressed) => {
-                                self.render_state.update_world_scale(self.render_state.get_world_scale() * 0.3);
+                            (Some(VirtualKeyCode::Z), ElementState::Pressed) if self.modifiers.ctrl() || self.modifiers.logo() => {
+                                match self.modifiers.shift() {
+                                    true => self.redo(),
+                                    false => self.undo(),
+                                }
+                            },
+                            (Some(VirtualKeyCode::S), ElementState::Pressed) if self.modifiers.ctrl() || self.modifiers.logo() => {
+                                // shift also downloads the session as a file
+                                self.save_session(self.modifiers.shift());
+                            },
+                            (Some(VirtualKeyCode::O), ElementState::Pressed) if self.modifiers.ctrl() || self.modifiers.logo() => {
+                                self.pending_session = Some(file_io::request_file(SESSION_FILE_NAME, ".wvs"));
+                            },
+                            (Some(VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd), ElementState::Pressed) => {
+                                self.render_state.zoom_around_clip_location(ZOOM_STEP, self.render_state.get_cursor_clip_location());
+                            },
+                            (Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract), ElementState::Pressed) => {
+                                self.render_state.zoom_around_clip_location(1.0 / ZOOM_STEP, self.render_state.get_cursor_clip_location());
+                            },
+                            (Some(VirtualKeyCode::Left), ElementState::Pressed) => self.render_state.pan_by_clip_distance([-PAN_STEP, 0.0]),
+                            (Some(VirtualKeyCode::Right), ElementState::Pressed) => self.render_state.pan_by_clip_distance([PAN_STEP, 0.0]),
+                            (Some(VirtualKeyCode::Up), ElementState::Pressed) => self.render_state.pan_by_clip_distance([0.0, PAN_STEP]),
+                            (Some(VirtualKeyCode::Down), ElementState::Pressed) => self.render_state.pan_by_clip_distance([0.0, -PAN_STEP]),
+                            (Some(VirtualKeyCode::F), ElementState::Pressed) => {
+                                self.render_state.fit_wave_to_view();
                             },
                             (Some(VirtualKeyCode::R), ElementState::Pressed) => {
                                 // export the current visual state, load the audio state with a new wave based on the exported state
-                                
-                            }
+                                self.finish_drag();
+                                let nodes = wave_shape_from_visual_state(self.render_state.export_visual_state());
+                                log::warn!("replacing the wave with {} in scope node(s)", nodes.len());
+                                let before = self.sound_engine.nodes();
+                                // the anchors that were off screen go too, so every anchor keeps the same index as its node
+                                self.show_nodes(&nodes);
+                                self.sound_engine.replace_nodes(nodes.clone());
+                                self.sync_harmonics();
+                                self.record_edit(EditCommand::ReplaceNodes { before, after:nodes });
+                                self.update_pitch_readout();
+                            },
+                            (Some(VirtualKeyCode::Delete | VirtualKeyCode::Back), ElementState::Pressed) => {
+                                self.remove_anchor_at_cursor();
+                            },
+                            (Some(VirtualKeyCode::G), ElementState::Pressed) => {
+                                let grid = self.render_state.grid();
+                                self.render_state.set_grid(rendering::Grid { visible:!grid.visible, ..grid });
+                            },
+                            // fewer or more grid columns, holding shift changes the rows instead
+                            (Some(VirtualKeyCode::Comma | VirtualKeyCode::Period), ElementState::Pressed) => {
+                                let change = match input.virtual_keycode {
+                                    Some(VirtualKeyCode::Period) => 1,
+                                    _ => -1,
+                                };
+                                match self.modifiers.shift() {
+                                    true => self.rescale_grid(0, change),
+                                    false => self.rescale_grid(change, 0),
+                                }
+                            },
+                            // plays or stops the wave on its own, for when there's no keyboard to play it from
+                            (Some(VirtualKeyCode::Space), ElementState::Pressed) => {
+                                match self.sound_engine.toggle_drone_gate() {
+                                    true => log::warn!("gate open"),
+                                    false => log::warn!("gate closed"),
+                                }
+                            },
+                            (Some(key @ (VirtualKeyCode::Key1 | VirtualKeyCode::Key2 | VirtualKeyCode::Key3 | VirtualKeyCode::Key4)), ElementState::Pressed) => {
+                                self.adjust_envelope(key);
+                            },
+                            (Some(VirtualKeyCode::Key5), ElementState::Pressed) => {
+                                let adsr = self.sound_engine.adsr();
+                                let curve = adsr.curve.next();
+                                self.sound_engine.set_adsr(audio::AdsrSettings { curve, ..adsr });
+                                log::warn!("envelope curve is now: {}", curve.name());
+                            },
+                            (Some(VirtualKeyCode::P), ElementState::Pressed) => {
+                                let steal_policy = self.sound_engine.steal_policy().next();
+                                self.sound_engine.set_steal_policy(steal_policy);
+                                log::warn!("new notes now take over the {} voice once all are playing", steal_policy.name());
+                            },
+                            // fewer or more notes that can play at once
+                            (Some(VirtualKeyCode::PageDown | VirtualKeyCode::PageUp), ElementState::Pressed) => {
+                                let max_voices = match input.virtual_keycode {
+                                    Some(VirtualKeyCode::PageUp) => self.sound_engine.max_voices() + 1,
+                                    _ => self.sound_engine.max_voices().saturating_sub(1),
+                                };
+                                self.sound_engine.set_max_voices(max_voices);
+                                log::warn!("up to {} voice(s) at once", self.sound_engine.max_voices());
+                            },
+                            (Some(VirtualKeyCode::Tab), ElementState::Pressed) => {
+                                self.switch_layer();
+                            },
+                            (Some(VirtualKeyCode::L), ElementState::Pressed) => {
+                                let settings = self.sound_engine.modulator_settings();
+                                let mode = settings.mode.next();
+                                self.sound_engine.set_modulator_settings(audio::ModulatorSettings { mode, ..settings });
+                                log::warn!("modulator mode is now: {}", mode.name());
+                            },
+                            // the modulation matrix: M picks a slot, N and K change where it routes from and to
+                            (Some(VirtualKeyCode::M), ElementState::Pressed) => {
+                                self.selected_route = (self.selected_route + 1) % audio::MAX_ROUTES;
+                                self.log_selected_route();
+                            },
+                            (Some(VirtualKeyCode::N), ElementState::Pressed) => {
+                                self.update_selected_route(|route| audio::ModRoute { source:route.source.next(), ..route });
+                            },
+                            (Some(VirtualKeyCode::K), ElementState::Pressed) => {
+                                let node_index = self.route_node_index();
+                                self.update_selected_route(|route| audio::ModRoute { target:route.target.next(node_index), ..route });
+                            },
+                            (Some(VirtualKeyCode::Key6), ElementState::Pressed) => {
+                                let step = if self.modifiers.shift() { -MOD_DEPTH_STEP } else { MOD_DEPTH_STEP };
+                                self.update_selected_route(|route| audio::ModRoute { depth:(route.depth + step).clamp(-1.0, 1.0), ..route });
+                            },
+                            (Some(key @ (VirtualKeyCode::Key7 | VirtualKeyCode::Key8 | VirtualKeyCode::Key9 | VirtualKeyCode::Key0)), ElementState::Pressed) => {
+                                self.adjust_lfo(key);
+                            },
+                            (Some(VirtualKeyCode::H), ElementState::Pressed) => {
+                                self.toggle_harmonic_mode();
+                            },
+                            (Some(VirtualKeyCode::V), ElementState::Pressed) => {
+                                self.show_scope = !self.show_scope;
+                            },
+                            (Some(VirtualKeyCode::I), ElementState::Pressed) => {
+                                match self.sound_engine.cycle_interpolation_mode() {
+                                    Some(mode) => {
+                                        log::warn!("interpolation mode is now: {}", mode.name());
+                                        self.show_interpolation();
+                                        self.update_share_link();
+                                    },
+                                    None => log::warn!("no {} to change the interpolation mode of yet", self.sound_engine.edit_layer().name()),
+                                }
+                            },
+                            (Some(VirtualKeyCode::B), ElementState::Pressed) => {
+                                match self.sound_engine.toggle_band_limited() {
+                                    Some(true) => log::warn!("band limited playback on"),
+                                    Some(false) => log::warn!("band limited playback off"),
+                                    None => log::warn!("no {} to change the playback of yet", self.sound_engine.edit_layer().name()),
+                                }
+                            },
+                            (Some(VirtualKeyCode::T), ElementState::Pressed) => {
+                                // stack the current shape as the next frame of the wavetable
+                                let num_frames = self.sound_engine.stack_wavetable_frame();
+                                log::warn!("{num_frames} wavetable frame(s) stacked");
+                            },
+                            (Some(VirtualKeyCode::E), ElementState::Pressed) => {
+                                // export the stacked frames (or just the current shape) as a wavetable, then start a new stack
+                                let mut bytes = vec![];
+                                match self.sound_engine.export_wavetable(&mut bytes, audio::LARGE_TABLE_LEN) {
+                                    Ok(num_frames) => {
+                                        match file_io::save_file("wavetable.wav", &bytes) {
+                                            Ok(()) => log::warn!("exported {num_frames} wavetable frame(s) to wavetable.wav"),
+                                            Err(err) => log::warn!("couldn't save wavetable: {err}"),
+                                        }
+                                        self.sound_engine.clear_wavetable_frames();
+                                    },
+                                    Err(err) => log::warn!("couldn't export wavetable: {err}"),
+                                }
+                            },
+                            (Some(VirtualKeyCode::O), ElementState::Pressed) => {
+                                // load a single cycle wav or wavetable and start editing from its shape
+                                self.pending_import = Some(file_io::request_file(IMPORT_FILE_NAME, ".wav"));
+                            },
+                            (Some(VirtualKeyCode::C), ElementState::Pressed) => {
+                                // change the curve of the segment that starts at the anchor under the cursor
+                                if let Some(index) = self.render_state.anchor_at_clip_location(self.render_state.get_cursor_clip_location()) {
+                                    let before = self.sound_engine.nodes().get(index).copied();
+                                    if let (Some(curve), Some(before)) = (self.sound_engine.cycle_node_curve(index), before) {
+                                        self.record_edit(EditCommand::ChangeCurve { wave_pos:before.wave_pos(), from:before.curve(), to:curve });
+                                        self.render_state.set_anchor_curve(index, curve.gpu_params());
+                                        log::warn!("segment curve at index {index} is now: {}", curve.name());
+                                    }
+                                }
+                            },
+                            (Some(VirtualKeyCode::LBracket | VirtualKeyCode::RBracket), ElementState::Pressed) => {
+                                // bend the curve of the segment that starts at the anchor under the cursor
+                                let delta = match input.virtual_keycode {
+                                    Some(VirtualKeyCode::RBracket) => 1.0,
+                                    _ => -1.0,
+                                };
+                                if let Some(index) = self.render_state.anchor_at_clip_location(self.render_state.get_cursor_clip_location()) {
+                                    let before = self.sound_engine.nodes().get(index).copied();
+                                    if let (Some(curve), Some(before)) = (self.sound_engine.adjust_node_curve_tension(index, delta), before) {
+                                        self.record_edit(EditCommand::ChangeCurve { wave_pos:before.wave_pos(), from:before.curve(), to:curve });
+                                        self.render_state.set_anchor_curve(index, curve.gpu_params());
+                                        log::warn!("segment curve at index {index} is now: {:?}", curve);
+                                    }
+                                }
+                            },
                             _ => {},
                         }
                     }
                     _ => {}
                 }
             }
+            // the next frame is about to be requested
+            Event::RedrawEventsCleared => {
+                self.refresh_scope();
+                self.refresh_spectrum();
+            },
             _ => {}
         }
     }
@@ -89,6 +929,10 @@ impl ShaperState {
         self.sound_engine.handle_audio_maintenance_events(event, control_flow);
         // sexy, handles events that change both the visual and audible state of the shaper
         self.handle_shaper_events(event, control_flow);
+        // files the user asked for earlier can arrive at any point
+        self.poll_pending_import();
+        self.poll_pending_session();
+        self.poll_midi();
     }
 }
 
@@ -104,6 +948,11 @@ pub async fn run() {
         render_state,
         sound_engine
     );
+    // a shape in the page URL wins over the stored session, that's what whoever shared the link wants heard,
+    // otherwise bring back the shape from the last time the session was saved
+    if !program_state.restore_share_link() {
+        program_state.restore_stored_session();
+    }
     
     event_loop.run( move |event, _, control_flow| {
         program_state.handle_event(&event, control_flow);
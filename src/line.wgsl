@@ -0,0 +1,25 @@
+// Lines drawn over the wave view, like the oscilloscope trace
+// the positions are worked out on the cpu and are already in clip space, so there is nothing to transform here
+
+struct LineOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color:vec3<f32>,
+};
+
+@vertex
+fn line_vert_main(
+    @location(0) position:vec2<f32>,
+    @location(1) color:vec3<f32>,
+) -> LineOutput {
+    var return_data:LineOutput;
+    return_data.position = vec4<f32>(position, 0.0, 1.0);
+    return_data.color = color;
+    return return_data;
+}
+
+@fragment
+fn line_frag_main(
+    line_data: LineOutput,
+) -> @location(0) vec4<f32> {
+    return vec4<f32>(line_data.color, 1.0);
+}
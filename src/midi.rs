@@ -0,0 +1,245 @@
+// MIDI input, so the shape can be played from a keyboard
+// Bytes come in from Web MIDI in the browser, or natively from whatever file, pipe or raw MIDI device
+// WAVESHAPER_MIDI_INPUT points at (e.g. /dev/snd/midiC1D0, or a recording to play back). Either way they end up in the
+// same queue and go through the same byte stream parser, so nothing past here cares where they came from.
+
+use std::sync::{Arc, Mutex};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{prelude::*, JsCast};
+
+// names the file or pipe to read MIDI bytes from when running natively
+#[cfg(not(target_arch = "wasm32"))]
+const MIDI_INPUT_ENV_VAR:&str = "WAVESHAPER_MIDI_INPUT";
+
+// the channel voice messages the shaper responds to, every channel is listened to
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MidiMessage {
+    NoteOn { channel:u8, note:u8, velocity:u8 },
+    // note ons with a velocity of 0 come out as note offs too
+    NoteOff { channel:u8, note:u8 },
+    ControlChange { channel:u8, controller:u8, value:u8 },
+    // -8192 to 8191, 0 is the wheel at rest
+    PitchBend { channel:u8, value:i16 },
+}
+
+// turns a raw MIDI byte stream into messages, a byte at a time
+// handles running status (data bytes that reuse the last status byte), skips system exclusive dumps, and lets
+// real time bytes (clock, start, stop...) through anywhere, even in the middle of a message
+#[derive(Default)]
+pub struct MidiParser {
+    status:Option<u8>,
+    data:Vec<u8>,
+    in_sysex:bool,
+}
+
+impl MidiParser {
+    pub fn push(&mut self, byte:u8) -> Option<MidiMessage> {
+        match byte {
+            // real time, doesn't touch the message in progress
+            0xF8..=0xFF => None,
+            0xF0 => {
+                self.in_sysex = true;
+                self.status = None;
+                None
+            },
+            0xF7 => {
+                self.in_sysex = false;
+                None
+            },
+            // system common messages cancel running status, their data bytes are dropped with no status to go with
+            0xF1..=0xF6 => {
+                self.in_sysex = false;
+                self.status = None;
+                None
+            },
+            0x80..=0xEF => {
+                self.in_sysex = false;
+                self.status = Some(byte);
+                self.data.clear();
+                None
+            },
+            _ => {
+                if self.in_sysex {
+                    return None
+                }
+                let status = self.status?;
+                self.data.push(byte);
+                if self.data.len() < data_len(status) {
+                    return None
+                }
+                // the status stays put for running status
+                let message = decode(status, &self.data);
+                self.data.clear();
+                message
+            },
+        }
+    }
+
+    pub fn parse(&mut self, bytes:&[u8]) -> Vec<MidiMessage> {
+        bytes.iter().filter_map(|byte| self.push(*byte)).collect()
+    }
+}
+
+// number of data bytes that follow a channel voice status byte
+fn data_len(status:u8) -> usize {
+    match status & 0xF0 {
+        0xC0 | 0xD0 => 1,
+        _ => 2,
+    }
+}
+
+fn decode(status:u8, data:&[u8]) -> Option<MidiMessage> {
+    let channel = status & 0x0F;
+    match (status & 0xF0, data) {
+        (0x80, &[note, _]) => Some(MidiMessage::NoteOff { channel, note }),
+        (0x90, &[note, 0]) => Some(MidiMessage::NoteOff { channel, note }),
+        (0x90, &[note, velocity]) => Some(MidiMessage::NoteOn { channel, note, velocity }),
+        (0xB0, &[controller, value]) => Some(MidiMessage::ControlChange { channel, controller, value }),
+        (0xE0, &[lsb, msb]) => Some(MidiMessage::PitchBend { channel, value:((msb as i16) << 7 | lsb as i16) - 8192 }),
+        // aftertouch and program changes aren't used
+        _ => None,
+    }
+}
+
+// bytes that have arrived from wherever MIDI is coming from, drained by poll() on the event loop
+pub struct MidiSource {
+    incoming:Arc<Mutex<Vec<u8>>>,
+    parser:MidiParser,
+}
+
+impl MidiSource {
+    // starts listening, nothing arrives if there's no MIDI to listen to
+    pub fn open() -> Self {
+        let incoming = Arc::new(Mutex::new(vec![]));
+        listen(incoming.clone());
+        MidiSource { incoming, parser:MidiParser::default() }
+    }
+
+    // messages completed since the last poll
+    pub fn poll(&mut self) -> Vec<MidiMessage> {
+        let bytes = std::mem::take(&mut *self.incoming.lock().unwrap());
+        self.parser.parse(&bytes)
+    }
+}
+
+// reads the file or pipe on a thread of its own, a pipe or device blocks until there is something to read
+#[cfg(not(target_arch = "wasm32"))]
+fn listen(incoming:Arc<Mutex<Vec<u8>>>) {
+    use std::io::Read;
+
+    let Ok(path) = std::env::var(MIDI_INPUT_ENV_VAR) else {
+        return
+    };
+    let mut file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(err) => {
+            log::warn!("couldn't open MIDI input {path}: {err}");
+            return
+        },
+    };
+    log::warn!("listening for MIDI on {path}");
+    std::thread::spawn(move || {
+        let mut chunk = [0u8; 256];
+        loop {
+            match file.read(&mut chunk) {
+                // the end of a file, or whoever was writing to the pipe is done
+                Ok(0) => break,
+                Ok(len) => incoming.lock().unwrap().extend_from_slice(&chunk[..len]),
+                Err(err) => {
+                    log::warn!("stopped reading MIDI input: {err}");
+                    break
+                },
+            }
+        }
+    });
+}
+
+// asks the browser for Web MIDI, then listens to every input that's plugged in at that point
+#[cfg(target_arch = "wasm32")]
+fn listen(incoming:Arc<Mutex<Vec<u8>>>) {
+    let Some(navigator) = web_sys::window().map(|win| win.navigator()) else {
+        return
+    };
+    let request = match navigator.request_midi_access() {
+        Ok(request) => request,
+        Err(err) => {
+            log::warn!("this browser doesn't do Web MIDI: {:?}", err);
+            return
+        },
+    };
+    wasm_bindgen_futures::spawn_local(async move {
+        let access = match wasm_bindgen_futures::JsFuture::from(request).await {
+            Ok(access) => access.unchecked_into::<web_sys::MidiAccess>(),
+            Err(err) => {
+                log::warn!("no access to MIDI: {:?}", err);
+                return
+            },
+        };
+        // the input map is a read only js Map, which is enough to walk through it
+        let inputs = access.inputs().unchecked_into::<js_sys::Map>();
+        inputs.for_each(&mut |input, _| {
+            let input = input.unchecked_into::<web_sys::MidiInput>();
+            let incoming = incoming.clone();
+            let on_message = Closure::<dyn FnMut(web_sys::MidiMessageEvent)>::new(move |event:web_sys::MidiMessageEvent| {
+                if let Ok(bytes) = event.data() {
+                    incoming.lock().unwrap().extend_from_slice(&bytes);
+                }
+            });
+            input.set_onmidimessage(Some(on_message.as_ref().unchecked_ref()));
+            // the browser calls this for every message from now on, so it has to outlive this function
+            on_message.forget();
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_status_reuses_the_last_status_byte() {
+        let messages = MidiParser::default().parse(&[0x91, 60, 100, 64, 90, 67, 80]);
+        assert_eq!(messages, vec![
+            MidiMessage::NoteOn { channel:1, note:60, velocity:100 },
+            MidiMessage::NoteOn { channel:1, note:64, velocity:90 },
+            MidiMessage::NoteOn { channel:1, note:67, velocity:80 },
+        ]);
+    }
+
+    #[test]
+    fn note_on_with_no_velocity_is_a_note_off() {
+        let messages = MidiParser::default().parse(&[0x90, 60, 100, 60, 0, 0x80, 62, 64]);
+        assert_eq!(messages, vec![
+            MidiMessage::NoteOn { channel:0, note:60, velocity:100 },
+            MidiMessage::NoteOff { channel:0, note:60 },
+            MidiMessage::NoteOff { channel:0, note:62 },
+        ]);
+    }
+
+    #[test]
+    fn sysex_is_skipped() {
+        // the data bytes inside the dump would make a note on if running status were still going
+        let messages = MidiParser::default().parse(&[0x90, 60, 100, 0xF0, 0x43, 60, 100, 0xF7, 62, 100, 0x90, 62, 100]);
+        assert_eq!(messages, vec![
+            MidiMessage::NoteOn { channel:0, note:60, velocity:100 },
+            MidiMessage::NoteOn { channel:0, note:62, velocity:100 },
+        ]);
+    }
+
+    #[test]
+    fn real_time_bytes_pass_through_a_message() {
+        let messages = MidiParser::default().parse(&[0xF8, 0x90, 0xF8, 60, 0xFA, 100, 0xF8]);
+        assert_eq!(messages, vec![MidiMessage::NoteOn { channel:0, note:60, velocity:100 }]);
+    }
+
+    #[test]
+    fn pitch_bend_is_centred_on_zero() {
+        let messages = MidiParser::default().parse(&[0xE2, 0x00, 0x40, 0x00, 0x00, 0x7F, 0x7F]);
+        assert_eq!(messages, vec![
+            MidiMessage::PitchBend { channel:2, value:0 },
+            MidiMessage::PitchBend { channel:2, value:-8192 },
+            MidiMessage::PitchBend { channel:2, value:8191 },
+        ]);
+    }
+}
@@ -11,6 +11,9 @@ use winit::{
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+mod line_overlay;
+use line_overlay::{LineOverlay, create_line_pipeline};
+
 // Structure that packages info needed for each instance of the vertex shader
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -26,6 +29,39 @@ struct GraphicsInput {
     cursor_position:[f32;4],
     world_to_clip_transfm:[[f32;4];4],
     canvas_dimensions:[u32;4],
+    // see Grid::gpu_params()
+    grid:[f32;4],
+    // [interpolation kind, unused, unused, unused], see audio::InterpolationMode::gpu_kind()
+    interpolation:[u32;4],
+}
+
+// Lines drawn behind the wave that anchors can be snapped to, the cycle (-1 to 1 in world x) is split into x_divisions
+// columns and the amplitude range (-1 to 1 in world y) into y_steps rows
+#[derive(Copy, Clone, Debug)]
+pub struct Grid {
+    pub x_divisions:u32,
+    pub y_steps:u32,
+    pub visible:bool,
+}
+impl Grid {
+    // packs the grid into the layout read by the shader: [x divisions, y steps, visible, unused]
+    fn gpu_params(&self) -> [f32;4] {
+        [self.x_divisions as f32, self.y_steps as f32, self.visible as u32 as f32, 0.0]
+    }
+
+    // moves a world position onto the nearest grid intersection
+    fn snap(&self, world_pos:[f32;2]) -> [f32;2] {
+        let snap_axis = |coord:f32, divisions:u32| {
+            let spacing = 2.0 / divisions.max(1) as f32;
+            ((coord + 1.0) / spacing).round() * spacing - 1.0
+        };
+        [snap_axis(world_pos[0], self.x_divisions), snap_axis(world_pos[1], self.y_steps)]
+    }
+}
+impl Default for Grid {
+    fn default() -> Self {
+        Grid { x_divisions:16, y_steps:8, visible:true }
+    }
 }
 
 // Structure that contains all info needed to render a segment of the visual representation of the wave, besides the actual geometry of the card
@@ -35,7 +71,12 @@ struct GraphicsInput {
 struct Anchor {
     position:[f32;3],
     right_nbr_pos:[f32;3],
-    scale:f32
+    scale:f32,
+    // shape of the segment to the right neighbor, packed as [kind, param a, param b, unused], see audio::SegmentCurve
+    curve:[f32;4],
+    // [left neighbor x, left neighbor y, right neighbor's right neighbor x, its y], the smooth interpolation modes look
+    // at the slope on either side of the segment
+    outer_nbr_pos:[f32;4],
 }
 impl Anchor {
     // returns a vertex buffer layout used for storing this data type in a Vertex Buffer
@@ -62,6 +103,16 @@ impl Anchor {
                     offset:(std::mem::size_of::<[f32; 3]>() * 2) as wgpu::BufferAddress,
                     shader_location:4,
                     format:wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute{
+                    offset:(std::mem::size_of::<[f32; 3]>() * 2 + std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+                    shader_location:5,
+                    format:wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute{
+                    offset:(std::mem::size_of::<[f32; 3]>() * 2 + std::mem::size_of::<f32>() + std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+                    shader_location:6,
+                    format:wgpu::VertexFormat::Float32x4,
                 }
             ]
         }
@@ -71,12 +122,42 @@ impl Anchor {
 // For now this struct is simply the way that Everything is packaged when I want to export the visual state of the wave
 // but in the future it couple be used for better decoupling of the visual wave state from the rendering engine, 
 // so that multiple visual layers can be rendered with the same engine setup
+// The editor already uses it that way for the layer it isn't showing, see State::import_visual_state
 // Stores the anchors and the coord system transforms, because they can be used to determine which anchors are in scope
 pub struct VisualState {
     anchors:Vec<Anchor>,
     world_to_clip:[[f32;4];4],
     clip_to_world:[[f32;4];4]
 }
+impl VisualState {
+    pub fn world_scale(&self) -> f32 {
+        self.world_to_clip[1][1]
+    }
+
+    // world position at the middle of the view
+    pub fn view_center(&self) -> [f32;2] {
+        let center = dot_product(self.clip_to_world, [0.0, 0.0, 0.0, 1.0]);
+        [center[0], center[1]]
+    }
+
+    // whether a world position is inside the part of the world that is on screen
+    fn in_scope(&self, world_pos:[f32;3]) -> bool {
+        let bottom_left = dot_product(self.clip_to_world, [-1.0, -1.0, 0.0, 1.0]);
+        let top_right = dot_product(self.clip_to_world, [1.0, 1.0, 0.0, 1.0]);
+        (bottom_left[0]..top_right[0]).contains(&world_pos[0]) && (bottom_left[1]..=top_right[1]).contains(&world_pos[1])
+    }
+
+    // world location and packed segment curve of every anchor that is on screen, in order of x position
+    // the right edge is left out, it's where the next cycle of the wave starts
+    pub fn anchors_in_scope(&self) -> impl Iterator<Item = ([f32;4], [f32;4])> + '_ {
+        self.anchors.iter()
+            .filter(|anchor| self.in_scope(anchor.position))
+            .map(|anchor| {
+                let [x, y, z] = anchor.position;
+                ([x, y, z, 1.0], anchor.curve)
+            })
+    }
+}
 
 // TODO: move this to a "shapes" module
 // define triangles that fill the screen
@@ -253,7 +334,8 @@ fn dot_product(transform_matrix:[[f32;4];4], vector:[f32;4]) -> [f32;4]{
     ]
 }
 
-fn create_clip_to_wrld_mat(world_scale:f32, aspect_ratio:f32) -> [[f32;4];4] {
+// view_center is the world position that ends up in the middle of the view
+fn create_clip_to_wrld_mat(world_scale:f32, aspect_ratio:f32, view_center:[f32;2]) -> [[f32;4];4] {
 
     // !!! WGSL INTERPRETS MATRICES AS SETS OF COLUMN VECTORS !!!
     // example: mat2x3 data type in wgsl is a matrix with 2 columns and 3 rows
@@ -262,23 +344,82 @@ fn create_clip_to_wrld_mat(world_scale:f32, aspect_ratio:f32) -> [[f32;4];4] {
         [1.0 / (aspect_ratio*world_scale),         0.0      , 0.0, 0.0],
         [               0.0              , 1.0 / world_scale, 0.0, 0.0],
         [               0.0              ,         0.0      , 1.0, 0.0],
-        [               0.0              ,         0.0      , 0.0, 1.0],
+        [         view_center[0]         ,   view_center[1] , 0.0, 1.0],
     ]
 }
 
-fn create_wrld_to_clip_mat(world_scale:f32, aspect_ratio:f32) -> [[f32;4];4] {
+fn create_wrld_to_clip_mat(world_scale:f32, aspect_ratio:f32, view_center:[f32;2]) -> [[f32;4];4] {
 
     // !!! WGSL INTERPRETS MATRICES AS SETS OF COLUMN VECTORS !!!
     // example: mat2x3 data type in wgsl is a matrix with 2 columns and 3 rows
     // https://gpuweb.github.io/gpuweb/wgsl/#matrix-types
     [
-        [aspect_ratio * world_scale,     0.0    , 0.0, 0.0],
-        [            0.0           , world_scale, 0.0, 0.0],
-        [            0.0           ,     0.0    , 1.0, 0.0],
-        [            0.0           ,     0.0    , 0.0, 1.0],
+        [        aspect_ratio * world_scale        ,            0.0              , 0.0, 0.0],
+        [                   0.0                    ,        world_scale          , 0.0, 0.0],
+        [                   0.0                    ,            0.0              , 1.0, 0.0],
+        [-view_center[0] * aspect_ratio * world_scale, -view_center[1] * world_scale, 0.0, 1.0],
     ]
 }
 
+// how far the view can be zoomed out and in
+const MIN_WORLD_SCALE:f32 = 0.05;
+const MAX_WORLD_SCALE:f32 = 50.0;
+// "fit wave to view" leaves this much of the view as a border around the wave
+const FIT_MARGIN:f32 = 0.9;
+// anchors can be picked up from this far away, in clip space so it's the same on screen at any zoom
+const ANCHOR_HIT_RADIUS:f32 = 0.1;
+// the scope trace is thinned out to at most this many points
+const MAX_SCOPE_POINTS:usize = 4096;
+const SCOPE_COLOR:[f32;3] = [0.2, 1.0, 0.4];
+
+// harmonic sliders split the editor into columns, each slider is a bar for the amplitude and a tick for the phase
+const MAX_HARMONIC_SLIDERS:usize = 64;
+// the tick is this much of a column wide
+const PHASE_TICK_WIDTH:f32 = 0.6;
+const HARMONIC_AMPLITUDE_COLOR:[f32;3] = [1.0, 0.6, 0.1];
+const HARMONIC_PHASE_COLOR:[f32;3] = [0.3, 0.7, 1.0];
+
+// the spectrum panel takes up this much of the canvas under the wave editor
+const SPECTRUM_PANEL_FRACTION:f32 = 0.25;
+// range of the panel's log frequency axis and dB axis
+const SPECTRUM_FREQ_RANGE:[f32;2] = [20.0, 20000.0];
+const SPECTRUM_DB_RANGE:[f32;2] = [-96.0, 0.0];
+// where the panel's axis lines are drawn
+const SPECTRUM_FREQ_LINES:&[f32] = &[100.0, 1000.0, 10000.0];
+const SPECTRUM_DB_LINES:&[f32] = &[-24.0, -48.0, -72.0];
+const MAX_SPECTRUM_POINTS:usize = 4096;
+const SPECTRUM_AXIS_COLOR:[f32;3] = [0.3, 0.3, 0.3];
+// harmonics of the drawn shape are white like the wave, the output is the same color as the scope trace it comes from
+const CYCLE_SPECTRUM_COLOR:[f32;3] = [1.0, 1.0, 1.0];
+const OUTPUT_SPECTRUM_COLOR:[f32;3] = SCOPE_COLOR;
+
+// height of the part of the canvas the wave editor is drawn in, the spectrum panel gets the rest
+fn editor_height(canvas_size:winit::dpi::PhysicalSize<u32>) -> u32 {
+    (canvas_size.height as f32 * (1.0 - SPECTRUM_PANEL_FRACTION)).round() as u32
+}
+
+// position of a frequency (Hz) and level (dB) in the clip space of the spectrum panel
+fn spectrum_clip_location(frequency:f32, level_db:f32) -> [f32;2] {
+    let [min_freq, max_freq] = SPECTRUM_FREQ_RANGE;
+    let [min_db, max_db] = SPECTRUM_DB_RANGE;
+    let x = (frequency / min_freq).log10() / (max_freq / min_freq).log10();
+    let y = (level_db.clamp(min_db, max_db) - min_db) / (max_db - min_db);
+    [x * 2.0 - 1.0, y * 2.0 - 1.0]
+}
+
+// room left under the canvas on the page for the readout
+#[cfg(target_arch = "wasm32")]
+const READOUT_HEIGHT:f64 = 32.0;
+
+// size the canvas should be to fill the browser window, leaving room for the readout
+#[cfg(target_arch = "wasm32")]
+fn page_size() -> Option<winit::dpi::LogicalSize<f64>> {
+    let window = web_sys::window()?;
+    let width = window.inner_width().ok()?.as_f64()?;
+    let height = window.inner_height().ok()?.as_f64()? - READOUT_HEIGHT;
+    Some(winit::dpi::LogicalSize::new(width, height.max(1.0)))
+}
+
 pub struct State {
     surface: wgpu::Surface,
     device: wgpu::Device,
@@ -289,10 +430,25 @@ pub struct State {
     window: Window,
 
     render_pipeline: wgpu::RenderPipeline,
+    line_pipeline: wgpu::RenderPipeline,
+    // trace of what the audio engine actually played, see set_scope_samples
+    scope_overlay: LineOverlay,
+    // sliders shown while editing harmonics, see set_harmonic_sliders
+    harmonic_amplitude_overlay: LineOverlay,
+    harmonic_phase_overlay: LineOverlay,
+    // axis lines, harmonics of the drawn shape and spectrum of the output, drawn in the panel under the editor
+    spectrum_axes_overlay: LineOverlay,
+    cycle_spectrum_overlay: LineOverlay,
+    output_spectrum_overlay: LineOverlay,
 
     cursor_pos:[f32;2],
+    grid:Grid,
+    // how the card fills in between anchors, packed by audio::InterpolationMode::gpu_kind()
+    interpolation:u32,
     aspect_ratio:f32,
     world_scale:f32,
+    // world position in the middle of the view
+    view_center:[f32;2],
     clip_to_world_transform:[[f32;4];4],
     world_to_clip_transform:[[f32;4];4],
     
@@ -437,29 +593,21 @@ impl State {
         };
 
         let cursor_pos:[f32;4] = [-1.0, 1.0, 0.0, 1.0];
-        let aspect_ratio: f32 = size.height as f32 / size.width as f32;
+        let aspect_ratio: f32 = editor_height(size) as f32 / size.width as f32;
 
         // !!! WGSL INTERPRETS MATRICES AS SETS OF COLUMN VECTORS !!!
         // example: mat2x3 data type in wgsl is a matrix with 2 columns and 3 rows
         // https://gpuweb.github.io/gpuweb/wgsl/#matrix-types
         let world_scale:f32 = 1.0;
-        let world_to_clip_transform:[[f32;4];4] = [
-            [aspect_ratio * world_scale,     0.0    , 0.0, 0.0],
-            [            0.0           , world_scale, 0.0, 0.0],
-            [            0.0           ,     0.0    , 1.0, 0.0],
-            [            0.0           ,     0.0    , 0.0, 1.0],
-        ];
-        let clip_to_world_transform:[[f32;4];4] = [
-            [1.0 / (aspect_ratio*world_scale),         0.0      , 0.0, 0.0],
-            [               0.0              , 1.0 / world_scale, 0.0, 0.0],
-            [               0.0              ,         0.0      , 1.0, 0.0],
-            [               0.0              ,         0.0      , 0.0, 1.0],
-        ];
+        let world_to_clip_transform = create_wrld_to_clip_mat(world_scale, aspect_ratio, [0.0, 0.0]);
+        let clip_to_world_transform = create_clip_to_wrld_mat(world_scale, aspect_ratio, [0.0, 0.0]);
 
         let graphics_input = GraphicsInput {
             cursor_position:cursor_pos,
             world_to_clip_transfm:world_to_clip_transform,
             canvas_dimensions:[size.height, size.width, 0, 0],
+            grid:Grid::default().gpu_params(),
+            interpolation:[0; 4],
         };
 
         // create uniform buffer for the cursor position and other info such as aspect ratio
@@ -548,6 +696,23 @@ impl State {
                 multiview: None, // ?? look into what "array textures" are, more than one surface texture?
             });
 
+        let line_pipeline = create_line_pipeline(&device, config.format);
+        let scope_overlay = LineOverlay::new(&device, MAX_SCOPE_POINTS, "Scope Line Buffer");
+        // a bar and a cap per amplitude slider
+        let harmonic_amplitude_overlay = LineOverlay::new(&device, MAX_HARMONIC_SLIDERS * 2, "Harmonic Amplitude Line Buffer");
+        let harmonic_phase_overlay = LineOverlay::new(&device, MAX_HARMONIC_SLIDERS, "Harmonic Phase Line Buffer");
+        let cycle_spectrum_overlay = LineOverlay::new(&device, MAX_SPECTRUM_POINTS, "Cycle Spectrum Line Buffer");
+        let output_spectrum_overlay = LineOverlay::new(&device, MAX_SPECTRUM_POINTS, "Output Spectrum Line Buffer");
+        // the axes never change
+        let mut spectrum_axes_overlay = LineOverlay::new(&device, SPECTRUM_FREQ_LINES.len() + SPECTRUM_DB_LINES.len() + 1, "Spectrum Axes Line Buffer");
+        let axis_lines = SPECTRUM_FREQ_LINES.iter()
+            .map(|frequency| [[spectrum_clip_location(*frequency, 0.0)[0], -1.0], [spectrum_clip_location(*frequency, 0.0)[0], 1.0]])
+            .chain(SPECTRUM_DB_LINES.iter().map(|level_db| [[-1.0, spectrum_clip_location(1.0, *level_db)[1]], [1.0, spectrum_clip_location(1.0, *level_db)[1]]]))
+            // border between the editor and the panel
+            .chain(std::iter::once([[-1.0, 1.0], [1.0, 1.0]]))
+            .collect::<Vec<_>>();
+        spectrum_axes_overlay.set_segments(&queue, &axis_lines, SPECTRUM_AXIS_COLOR);
+
         Self {
             surface,
             device,
@@ -558,13 +723,23 @@ impl State {
             window,
             
             render_pipeline,
+            line_pipeline,
+            scope_overlay,
+            harmonic_amplitude_overlay,
+            harmonic_phase_overlay,
+            spectrum_axes_overlay,
+            cycle_spectrum_overlay,
+            output_spectrum_overlay,
 
             aspect_ratio,
             world_scale,
+            view_center:[0.0, 0.0],
             clip_to_world_transform,
             world_to_clip_transform,
 
             cursor_pos:[0.0, 0.0],
+            grid:Grid::default(),
+            interpolation:0,
             graphics_input_buffer,
             
             uniform_bind_group,
@@ -584,14 +759,20 @@ impl State {
         self.world_to_clip_transform[1][1]
     }
 
-    pub fn update_world_scale(&mut self, new_scale:f32) {
-        self.clip_to_world_transform = create_clip_to_wrld_mat(new_scale, 1.0); // Aspect Ratio will need to change with support for non square
-        self.world_to_clip_transform = create_wrld_to_clip_mat(new_scale, 1.0); // Aspect Ratio will need to change with support for non square
+    // moves the view so that view_center is in the middle of it, and zooms it to new_scale (clamped)
+    pub fn update_view(&mut self, new_scale:f32, view_center:[f32;2]) {
+        let new_scale = new_scale.clamp(MIN_WORLD_SCALE, MAX_WORLD_SCALE);
+        self.world_scale = new_scale;
+        self.view_center = view_center;
+        self.clip_to_world_transform = create_clip_to_wrld_mat(new_scale, self.aspect_ratio, view_center);
+        self.world_to_clip_transform = create_wrld_to_clip_mat(new_scale, self.aspect_ratio, view_center);
 
         let graphics_input = GraphicsInput {
             cursor_position:[self.cursor_pos[0], self.cursor_pos[1], 0.0, 0.5],
-            world_to_clip_transfm:create_wrld_to_clip_mat(new_scale, 1.0),
+            world_to_clip_transfm:self.world_to_clip_transform,
             canvas_dimensions:[self.size.height, self.size.width, 0, 0],
+            grid:self.grid.gpu_params(),
+            interpolation:[self.interpolation, 0, 0, 0],
         };
         
         self.queue.write_buffer(
@@ -601,6 +782,70 @@ impl State {
         );
     }
 
+    // zooms by a factor (above 1 zooms in) while keeping the world position under clip_loc where it is on screen
+    pub fn zoom_around_clip_location(&mut self, factor:f32, clip_loc:[f32;4]) {
+        let old_scale = self.get_world_scale();
+        let new_scale = (old_scale * factor).clamp(MIN_WORLD_SCALE, MAX_WORLD_SCALE);
+        let world_loc = dot_product(self.clip_to_world_transform, clip_loc);
+        let ratio = old_scale / new_scale;
+        let view_center = [
+            world_loc[0] - (world_loc[0] - self.view_center[0]) * ratio,
+            world_loc[1] - (world_loc[1] - self.view_center[1]) * ratio,
+        ];
+        self.update_view(new_scale, view_center);
+    }
+
+    // moves the view by a distance in clip space, so a step is the same size on screen at any zoom
+    pub fn pan_by_clip_distance(&mut self, clip_dist:[f32;2]) {
+        let origin = dot_product(self.clip_to_world_transform, [0.0, 0.0, 0.0, 1.0]);
+        let moved = dot_product(self.clip_to_world_transform, [clip_dist[0], clip_dist[1], 0.0, 1.0]);
+        let view_center = [self.view_center[0] + moved[0] - origin[0], self.view_center[1] + moved[1] - origin[1]];
+        self.update_view(self.get_world_scale(), view_center);
+    }
+
+    // centers the view on the wave cycle (-1 to 1 in world x) and zooms so that it and every anchor fit
+    pub fn fit_wave_to_view(&mut self) {
+        let max_amplitude = self.anchor_instances.iter().map(|anchor| anchor.position[1].abs()).fold(1.0, f32::max);
+        // the view is 2 / (aspect_ratio * world_scale) world units wide and 2 / world_scale tall
+        let scale_to_fit_width = FIT_MARGIN / self.aspect_ratio;
+        let scale_to_fit_height = FIT_MARGIN / max_amplitude;
+        self.update_view(scale_to_fit_width.min(scale_to_fit_height), [0.0, 0.0]);
+    }
+
+    pub fn grid(&self) -> Grid {
+        self.grid
+    }
+
+    // the card has to fill in between anchors the same way the wave does, or it would show a different shape to the one
+    // that is heard
+    pub fn set_interpolation(&mut self, interpolation:u32) {
+        self.interpolation = interpolation;
+        // the interpolation kind sits right after the grid in the graphics input
+        let offset = std::mem::size_of::<[f32;4]>() * 2 + std::mem::size_of::<[[f32;4];4]>() + std::mem::size_of::<[u32;4]>();
+        self.queue.write_buffer(
+            &self.graphics_input_buffer,
+            offset as wgpu::BufferAddress,
+            bytemuck::bytes_of(&[interpolation, 0, 0, 0])
+        );
+    }
+
+    pub fn set_grid(&mut self, grid:Grid) {
+        self.grid = grid;
+        // the grid sits right after the cursor, transform and canvas size in the graphics input
+        let offset = std::mem::size_of::<[f32;4]>() + std::mem::size_of::<[[f32;4];4]>() + std::mem::size_of::<[u32;4]>();
+        self.queue.write_buffer(
+            &self.graphics_input_buffer,
+            offset as wgpu::BufferAddress,
+            bytemuck::bytes_of(&grid.gpu_params())
+        );
+    }
+
+    // moves a world location onto the nearest grid intersection
+    pub fn snap_world_location(&self, world_loc:[f32;4]) -> [f32;4] {
+        let [x, y] = self.grid.snap([world_loc[0], world_loc[1]]);
+        [x, y, world_loc[2], world_loc[3]]
+    }
+
     fn update_anchor_instances_buf(&mut self){
         // Write the entire instances buffer again to new buffer 
         // TODO: this is bad, use offset instead if there is extra capacity, reset the buff once it has reach capacity
@@ -614,11 +859,14 @@ impl State {
         );
     }
 
-    pub fn add_circle_instance(&mut self, world_position:[f32;3], scale:f32) {
+    // returns whether the anchor went in, it doesn't if there is already one at the same x
+    pub fn add_circle_instance(&mut self, world_position:[f32;3], scale:f32) -> bool {
         let new_circle = Anchor {
             position:world_position,
             right_nbr_pos:[world_position[0], 0.0, 0.0], //TODO: placeholder neighbor pos
             scale:scale,
+            curve:[0.0;4], // straight line to the neighbor until told otherwise
+            outer_nbr_pos:[0.0;4],
         };
 
         // if empty list, populate the head, else search for place within list where this fits
@@ -633,17 +881,14 @@ impl State {
                     Ok(index) => {
                         // binary search was able to find an element at this exact position in the node list, don't add
                         log::warn!("Error: there is already a circle at position: {} not adding node to list", new_circle.position[0]);
+                        return false
                     },
                     Err(index) => {
                         // binary search could not find a node at this wave position, tells us the index of where it 
                         // would be in the list if it existed, use that to insert the node and preserve sort by wave pos
                         self.anchor_instances.insert(index, new_circle);
-                        // update the right neighbor
-                        self.anchor_instances[index].right_nbr_pos = self.anchor_instances[(index + 1) % self.anchor_instances.len()].position;
-                        // update the right neighbor of this new nodes left neighbor
-                        // extra logic allows end of list to have right neighbor at begining of list
-                        let num_circs = self.anchor_instances.len(); // need this bc we cant borrow within borrow on next line
-                        self.anchor_instances[(index + num_circs - 1) % num_circs].right_nbr_pos = self.anchor_instances[index].position;
+                        // the neighbors on both sides (and theirs) now see the new anchor
+                        self.relink_anchor_neighbors();
                         log::warn!("node added at index: {}", index);
                     }
                 }
@@ -653,6 +898,68 @@ impl State {
         log::warn!("Content of instances is: {:?}", self.anchor_instances);
 
         self.update_anchor_instances_buf();
+        true
+    }
+
+    // points every anchor's right_nbr_pos at the anchor after it and outer_nbr_pos at the anchors either side of its
+    // segment, wrapping around the ends of the list
+    fn relink_anchor_neighbors(&mut self) {
+        let num_circs = self.anchor_instances.len();
+        for index in 0..num_circs {
+            let left = self.anchor_instances[(index + num_circs - 1) % num_circs].position;
+            let second_right = self.anchor_instances[(index + 2) % num_circs].position;
+            self.anchor_instances[index].right_nbr_pos = self.anchor_instances[(index + 1) % num_circs].position;
+            self.anchor_instances[index].outer_nbr_pos = [left[0], left[1], second_right[0], second_right[1]];
+        }
+    }
+
+    // removes the anchor at anchor_index and closes the gap by linking its neighbors to each other
+    pub fn remove_anchor(&mut self, anchor_index:usize) -> bool {
+        if anchor_index >= self.anchor_instances.len() {
+            return false
+        }
+        self.anchor_instances.remove(anchor_index);
+        self.relink_anchor_neighbors();
+        self.update_anchor_instances_buf();
+        true
+    }
+
+    // moves the anchor at anchor_index to a new world location, keeping the anchors sorted by x position
+    // returns the index the anchor ended up at, or None if there is no such anchor or another one already sits at that x
+    pub fn move_anchor_to_world_location(&mut self, anchor_index:usize, world_loc:[f32;4]) -> Option<usize> {
+        if anchor_index >= self.anchor_instances.len() {
+            return None
+        }
+        if self.anchor_instances.iter().enumerate().any(|(index, anchor)| index != anchor_index && anchor.position[0] == world_loc[0]) {
+            return None
+        }
+
+        let mut anchor = self.anchor_instances.remove(anchor_index);
+        anchor.position = [world_loc[0], world_loc[1], world_loc[2]];
+        let new_index = self.anchor_instances.partition_point(|probe| probe.position[0] < anchor.position[0]);
+        self.anchor_instances.insert(new_index, anchor);
+        self.relink_anchor_neighbors();
+        self.update_anchor_instances_buf();
+        Some(new_index)
+    }
+
+    // throws away every anchor and replaces them with anchors at the given world locations, each with the packed
+    // curve of the segment that starts at it
+    pub fn replace_anchors_from_world_locations(&mut self, anchors:&[([f32;4], [f32;4])]) {
+        self.anchor_instances = anchors.iter()
+            .map(|(world_loc, curve)| {
+                Anchor {
+                    position:[world_loc[0], world_loc[1], world_loc[2]],
+                    right_nbr_pos:[world_loc[0], 0.0, 0.0],
+                    scale:1.0,
+                    curve:*curve,
+                    outer_nbr_pos:[0.0;4],
+                }
+            })
+            .collect();
+        self.anchor_instances.sort_by(|a, b| a.position[0].total_cmp(&b.position[0]));
+        self.relink_anchor_neighbors();
+        self.update_anchor_instances_buf();
     }
 
     // copy the visual state of the wave and shift it to the end of the current wave
@@ -694,6 +1001,14 @@ impl State {
         None
     }
 
+    // replaces the packed curve of the segment that starts at the anchor at anchor_index
+    pub fn set_anchor_curve(&mut self, anchor_index:usize, curve:[f32;4]) {
+        if let Some(anchor) = self.anchor_instances.get_mut(anchor_index) {
+            anchor.curve = curve;
+            self.update_anchor_instances_buf();
+        }
+    }
+
     pub fn expand_circle(&mut self, circle_index:usize) -> Result<(), &str> {
         if circle_index < self.anchor_instances.len() {
             // do something visually to the circle now that it has been clicked? idk
@@ -701,6 +1016,23 @@ impl State {
         Err("index out of bounds")
     }
 
+    // shows a short line of status text, in the browser it goes in the readout element next to the canvas
+    // natively it goes in the title bar
+    pub fn set_readout(&self, text:&str) {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                if let Some(readout) = web_sys::window()
+                    .and_then(|win| win.document())
+                    .and_then(|doc| doc.get_element_by_id("readout"))
+                {
+                    readout.set_text_content(Some(text));
+                }
+            } else {
+                self.window.set_title(text);
+            }
+        }
+    }
+
     pub fn window(&self) -> &Window {
         &self.window
     }
@@ -711,31 +1043,45 @@ impl State {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
-        }
 
-        // TODO: write new size to graphics input
+            // keep world units square on screen, rebuilding the transforms also writes the new size to the graphics input
+            self.aspect_ratio = editor_height(new_size) as f32 / new_size.width as f32;
+            self.update_view(self.get_world_scale(), self.view_center);
+        }
     }
 
     pub fn get_cursor_clip_location(&self) -> [f32;4] {
         let cursor_clip_x = ((self.cursor_pos[0] / self.size.width as f32 ) - 0.5) * 2.0;
-        let cursor_clip_y = ((self.cursor_pos[1] / self.size.height as f32) - 0.5) * -2.0;
+        let cursor_clip_y = ((self.cursor_pos[1] / editor_height(self.size) as f32) - 0.5) * -2.0;
         [cursor_clip_x, cursor_clip_y, 0.0, 1.0]
     }
 
-    pub fn add_circle_at_clip_location(&mut self, clip_loc:[f32;4]) -> bool {
-                
+    // false while the cursor is over the spectrum panel
+    pub fn cursor_in_editor(&self) -> bool {
+        self.cursor_pos[1] < editor_height(self.size) as f32
+    }
+
+    pub fn get_cursor_world_location(&self) -> [f32;4] {
+        dot_product(self.clip_to_world_transform, self.get_cursor_clip_location())
+    }
+
+    // index of the anchor under a position in clip space (usually the mouse location) if there is one
+    pub fn anchor_at_clip_location(&self, clip_loc:[f32;4]) -> Option<usize> {
         let world_loc = dot_product(self.clip_to_world_transform, clip_loc);
-            
+        self.circle_at_location([world_loc[0], world_loc[1]], ANCHOR_HIT_RADIUS / self.get_world_scale())
+    }
+
+    // returns whether a new anchor was added, pressing on an anchor or at the x of one adds nothing
+    pub fn add_circle_at_world_location(&mut self, world_loc:[f32;4]) -> bool {
         // determine whether the clicked position is within an existing circle
-        match self.circle_at_location([world_loc[0], world_loc[1]], 0.1) {
+        match self.anchor_at_clip_location(dot_product(self.world_to_clip_transform, world_loc)) {
             Some(index) => {
                 log::warn!("Clicked circle at index: {index}");
                 false
             },
             None => {
                 log::warn!("new circle created at world location: {:?}", world_loc);
-                self.add_circle_instance([world_loc[0], world_loc[1], world_loc[2]], 1.0);
-                true
+                self.add_circle_instance([world_loc[0], world_loc[1], world_loc[2]], 1.0)
             }
         }
     }
@@ -767,11 +1113,6 @@ impl State {
         }
     }
 
-    // TODO: function that updates world scale, clamped. Exposed so that lib.rs can use it to zoom in and out on the wave
-    // pub fn update_world_scale(&mut self) {
-
-    // }
-
     pub async fn init_rendering() -> (EventLoop<()>, State) {
         cfg_if::cfg_if! {
             if #[cfg(target_arch = "wasm32")] {
@@ -785,13 +1126,13 @@ impl State {
         let event_loop = EventLoop::new();
         let window = WindowBuilder::new().build(&event_loop).unwrap();
     
-        //#[cfg(target_arch = "wasm32")]
-        //{
-            // TODO: how can we pass the dimnsions of the window from javascript so that the UI takes up the whole screen?
+        #[cfg(target_arch = "wasm32")]
+        {
             // Winit prevents sizing with CSS, so we have to set
             // the size manually when on web.
-            use winit::dpi::PhysicalSize;
-            window.set_inner_size(PhysicalSize::new(800, 800));
+            if let Some(page_size) = page_size() {
+                window.set_inner_size(page_size);
+            }
     
             use winit::platform::web::WindowExtWebSys;
     
@@ -804,7 +1145,7 @@ impl State {
                     Some(())
                 })
                 .expect("Couldn't append canvas to document body.");
-        //}
+        }
 
         let render_state = Self::new(window).await;
 
@@ -847,6 +1188,10 @@ impl State {
                 depth_stencil_attachment: None,
             });
 
+            // the editor is drawn in the top of the canvas, clip space covers just that part
+            let editor_height = editor_height(self.size) as f32;
+            render_pass.set_viewport(0.0, 0.0, self.size.width as f32, editor_height, 0.0, 1.0);
+
             render_pass.set_pipeline(&self.render_pipeline);
 
             // Designate a vertex buffer
@@ -864,6 +1209,18 @@ impl State {
                 render_pass.draw_indexed(0..6, 0, 0..1); // draw background, remember range is not max inclusive
                 render_pass.draw_indexed(6..self.num_tri_indices, 0, 0..self.anchor_instances.len() as u32); // draw circles
             }
+
+            // overlays go on top of the wave
+            render_pass.set_pipeline(&self.line_pipeline);
+            self.scope_overlay.draw(&mut render_pass);
+            self.harmonic_amplitude_overlay.draw(&mut render_pass);
+            self.harmonic_phase_overlay.draw(&mut render_pass);
+
+            // then the spectrum panel in the rest of it
+            render_pass.set_viewport(0.0, editor_height, self.size.width as f32, self.size.height as f32 - editor_height, 0.0, 1.0);
+            self.spectrum_axes_overlay.draw(&mut render_pass);
+            self.cycle_spectrum_overlay.draw(&mut render_pass);
+            self.output_spectrum_overlay.draw(&mut render_pass);
         }
 
         self.queue.submit(std::iter::once(encoder.finish()));
@@ -872,6 +1229,19 @@ impl State {
         Ok(())
     }
 
+    // the browser doesn't tell winit when the page is resized, so the canvas is checked against the page every frame
+    #[cfg(target_arch = "wasm32")]
+    fn fit_canvas_to_page(&mut self) {
+        let Some(page_size) = page_size() else {
+            return
+        };
+        let new_size:winit::dpi::PhysicalSize<u32> = page_size.to_physical(self.window.scale_factor());
+        if new_size != self.size {
+            self.window.set_inner_size(page_size);
+            self.resize(new_size);
+        }
+    }
+
     pub fn handle_window_maintenance_events(&mut self, event:&Event<()>, control_flow:&mut ControlFlow) {
         match event {
             Event::WindowEvent {
@@ -917,6 +1287,8 @@ impl State {
                 }
             }
             Event::RedrawEventsCleared => {
+                #[cfg(target_arch = "wasm32")]
+                self.fit_canvas_to_page();
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
                 self.window().request_redraw();
@@ -925,14 +1297,91 @@ impl State {
         }
     }
 
-    // TODO: will be used by lib rs to get a simplified version of the current state
-    // pub fn export_visual_state(&self) -> VisualState {
-    //     VisualState { 
-    //         anchors:self.anchor_instances,
-    //         world_to_clip: self.world_to_clip_transform,
-    //         clip_to_world: self.clip_to_world_transform
-    //     }
-    // }
+    // draws the samples oldest to newest across the whole width of the view, at the same height an anchor with that
+    // amplitude would be drawn, so the trace can be held up against the shape it is supposed to follow
+    pub fn set_scope_samples(&mut self, samples:&[f32]) {
+        if samples.len() < 2 {
+            self.scope_overlay.clear();
+            return
+        }
+        // long traces are thinned out, there aren't enough pixels across to show every sample anyway
+        let stride = samples.len().div_ceil(MAX_SCOPE_POINTS);
+        let last_index = (samples.len() - 1) as f32;
+        let points = samples.iter()
+            .enumerate()
+            .step_by(stride)
+            .map(|(index, sample)| {
+                let clip_y = self.world_to_clip_transform[1][1] * sample + self.world_to_clip_transform[3][1];
+                [index as f32 / last_index * 2.0 - 1.0, clip_y]
+            })
+            .collect::<Vec<_>>();
+        self.scope_overlay.set_strip(&self.queue, &points, SCOPE_COLOR);
+    }
+
+    // draws a column of the editor per slider, each given as [amplitude, phase] scaled to 0 to 1 from the bottom of the
+    // view to the top. No sliders hides them
+    pub fn set_harmonic_sliders(&mut self, sliders:&[[f32;2]]) {
+        let column_width = 2.0 / sliders.len().max(1) as f32;
+        let height = |value:f32| value.clamp(0.0, 1.0) * 2.0 - 1.0;
+        let mut amplitude_lines = vec![];
+        let mut phase_lines = vec![];
+        for (index, [amplitude, phase]) in sliders.iter().enumerate() {
+            let left = -1.0 + column_width * index as f32;
+            let center = left + column_width / 2.0;
+            let tick_half_width = column_width * PHASE_TICK_WIDTH / 2.0;
+            amplitude_lines.push([[center, -1.0], [center, height(*amplitude)]]);
+            amplitude_lines.push([[left, height(*amplitude)], [left + column_width, height(*amplitude)]]);
+            phase_lines.push([[center - tick_half_width, height(*phase)], [center + tick_half_width, height(*phase)]]);
+        }
+        self.harmonic_amplitude_overlay.set_segments(&self.queue, &amplitude_lines, HARMONIC_AMPLITUDE_COLOR);
+        self.harmonic_phase_overlay.set_segments(&self.queue, &phase_lines, HARMONIC_PHASE_COLOR);
+    }
+
+    // which of num_sliders sliders a position in clip space is over, and the 0 to 1 value at that height
+    pub fn harmonic_slider_at_clip_location(&self, clip_loc:[f32;4], num_sliders:usize) -> Option<(usize, f32)> {
+        if num_sliders == 0 || clip_loc[0].abs() > 1.0 {
+            return None
+        }
+        let index = (((clip_loc[0] + 1.0) / 2.0 * num_sliders as f32) as usize).min(num_sliders - 1);
+        Some((index, ((clip_loc[1] + 1.0) / 2.0).clamp(0.0, 1.0)))
+    }
+
+    // takes spectra as [frequency in Hz, level in dB] pairs. The harmonics of the drawn shape are drawn as bars, the
+    // output as a line, both on a log frequency axis
+    pub fn set_spectra(&mut self, cycle_spectrum:&[[f32;2]], output_spectrum:&[[f32;2]]) {
+        let floor = SPECTRUM_DB_RANGE[0];
+        let bars = cycle_spectrum.iter()
+            .map(|[frequency, level_db]| [spectrum_clip_location(*frequency, floor), spectrum_clip_location(*frequency, *level_db)])
+            .collect::<Vec<_>>();
+        self.cycle_spectrum_overlay.set_segments(&self.queue, &bars, CYCLE_SPECTRUM_COLOR);
+
+        // bins below the axis are left out, a log axis has nowhere to put them
+        let line = output_spectrum.iter()
+            .filter(|[frequency, _]| *frequency >= SPECTRUM_FREQ_RANGE[0])
+            .map(|[frequency, level_db]| spectrum_clip_location(*frequency, *level_db))
+            .collect::<Vec<_>>();
+        self.output_spectrum_overlay.set_strip(&self.queue, &line, OUTPUT_SPECTRUM_COLOR);
+    }
+
+    // used by lib rs to get a simplified version of the current state
+    pub fn export_visual_state(&self) -> VisualState {
+        VisualState { 
+            anchors:self.anchor_instances.clone(),
+            world_to_clip: self.world_to_clip_transform,
+            clip_to_world: self.clip_to_world_transform
+        }
+    }
+
+    // puts back anchors and a view that were exported earlier, so the editor can swap between layers that each keep
+    // their own
+    pub fn import_visual_state(&mut self, visual_state:VisualState) {
+        let world_scale = visual_state.world_scale();
+        let view_center = visual_state.view_center();
+        self.anchor_instances = visual_state.anchors;
+        self.relink_anchor_neighbors();
+        self.update_anchor_instances_buf();
+        self.update_view(world_scale, view_center);
+    }
 }
 
 
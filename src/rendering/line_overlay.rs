@@ -0,0 +1,124 @@
+// Lines drawn on top of everything else, with their own pipeline so they don't have to squeeze into the card shader
+// Every overlay is a list of line segments (pairs of verts) in clip space, rewritten whenever what it shows changes.
+// The buffer is made once with a fixed capacity, anything past it is dropped.
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    pub position:[f32;2],
+    pub color:[f32;3],
+}
+impl LineVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset:0,
+                    shader_location:0,
+                    format:wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset:std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location:1,
+                    format:wgpu::VertexFormat::Float32x3,
+                },
+            ]
+        }
+    }
+}
+
+// one pipeline is shared by every overlay
+pub fn create_line_pipeline(device:&wgpu::Device, format:wgpu::TextureFormat) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../line.wgsl"));
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Line Pipeline Layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Line Pipeline"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "line_vert_main",
+            buffers: &[LineVertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "line_frag_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            // every two verts is a separate segment, lines are always one pixel wide on WebGL
+            topology: wgpu::PrimitiveTopology::LineList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
+
+pub struct LineOverlay {
+    buffer:wgpu::Buffer,
+    capacity:usize,
+    num_vertices:u32,
+}
+impl LineOverlay {
+    // capacity is counted in segments
+    pub fn new(device:&wgpu::Device, capacity:usize, label:&str) -> Self {
+        let capacity = capacity * 2;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        LineOverlay { buffer, capacity, num_vertices:0 }
+    }
+
+    // draws a line through every point in order
+    pub fn set_strip(&mut self, queue:&wgpu::Queue, points:&[[f32;2]], color:[f32;3]) {
+        let segments = points.windows(2).map(|pair| [pair[0], pair[1]]).collect::<Vec<_>>();
+        self.set_segments(queue, &segments, color);
+    }
+
+    // draws each pair of points as a separate segment
+    pub fn set_segments(&mut self, queue:&wgpu::Queue, segments:&[[[f32;2];2]], color:[f32;3]) {
+        let vertices = segments.iter()
+            .flatten()
+            .take(self.capacity)
+            .map(|position| LineVertex { position:*position, color })
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&vertices));
+        self.num_vertices = vertices.len() as u32;
+    }
+
+    pub fn clear(&mut self) {
+        self.num_vertices = 0;
+    }
+
+    // expects the line pipeline to already be set on the render pass
+    pub fn draw<'a>(&'a self, render_pass:&mut wgpu::RenderPass<'a>) {
+        if self.num_vertices == 0 {
+            return
+        }
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
@@ -0,0 +1,329 @@
+// Saved sessions: everything needed to get back the same shape, sound and view after a restart or a page refresh
+// Stored as short lines of text, one setting per line, so old sessions stay readable as settings get added. The first
+// line holds the format version, keys this version doesn't know about are skipped so small additions don't need a bump.
+//
+// wasm-waveshaper session 1
+// interpolation cubic-hermite
+// band-limited true
+// world-scale 1
+// view-center 0 0
+// frequency 110
+// max-voices 8
+// voice-stealing oldest
+// envelope <attack seconds> <decay seconds> <sustain level> <release seconds> <linear or exponential>
+// modulator <one-shot or loop> <seconds>
+// modulator-interpolation linear
+// lfo <1 or 2> <shape> <rate in Hz>
+// route <slot from 1> <source> <target> <depth>
+// node <wave pos> <amplitude> <curve kind> <curve param a> <curve param b>
+// modulator-node <wave pos> <amplitude> <curve kind> <curve param a> <curve param b>
+
+use crate::audio::{
+    AdsrSettings, EnvelopeCurve, InterpolationMode, LfoSettings, LfoShape, ModMatrix, ModRoute, ModSource, ModTarget, ModulatorMode,
+    ModulatorSettings, SegmentCurve, StealPolicy, WaveNode,
+};
+
+const HEADER:&str = "wasm-waveshaper session";
+// bump when a change would make older versions of the app misread a session
+pub const SESSION_VERSION:u32 = 1;
+// sessions saved before voices were added play this many at once
+const DEFAULT_MAX_VOICES:usize = 8;
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub nodes:Vec<WaveNode>,
+    pub interpolation:InterpolationMode,
+    pub band_limited:bool,
+    // None when there was no wave playing
+    pub frequency:Option<f32>,
+    pub world_scale:f32,
+    // world position in the middle of the view
+    pub view_center:[f32;2],
+    // how many keyboard notes can play at once, and which one gives way past that
+    pub max_voices:usize,
+    pub steal_policy:StealPolicy,
+    pub adsr:AdsrSettings,
+    // shape drawn on the modulator layer, empty if nothing was drawn there
+    pub modulator_nodes:Vec<WaveNode>,
+    pub modulator_interpolation:InterpolationMode,
+    pub modulator:ModulatorSettings,
+    pub mod_matrix:ModMatrix,
+}
+
+impl Session {
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            format!("{HEADER} {SESSION_VERSION}"),
+            format!("interpolation {}", self.interpolation.name().replace(' ', "-")),
+            format!("band-limited {}", self.band_limited),
+            format!("world-scale {}", self.world_scale),
+            format!("view-center {} {}", self.view_center[0], self.view_center[1]),
+        ];
+        if let Some(frequency) = self.frequency {
+            lines.push(format!("frequency {frequency}"));
+        }
+        lines.push(format!("max-voices {}", self.max_voices));
+        lines.push(format!("voice-stealing {}", self.steal_policy.name()));
+        let adsr = self.adsr;
+        lines.push(format!("envelope {} {} {} {} {}", adsr.attack, adsr.decay, adsr.sustain, adsr.release, adsr.curve.name()));
+        lines.push(format!("modulator {} {}", self.modulator.mode.name(), self.modulator.seconds));
+        lines.push(format!("modulator-interpolation {}", self.modulator_interpolation.name().replace(' ', "-")));
+        for (index, lfo) in self.mod_matrix.lfos.iter().enumerate() {
+            lines.push(format!("lfo {} {} {}", index + 1, lfo.shape.name(), lfo.rate));
+        }
+        for (index, route) in self.mod_matrix.routes.iter().enumerate() {
+            lines.push(format!("route {} {} {} {}", index + 1, route.source.name(), route.target.name(), route.depth));
+        }
+        lines.extend(self.nodes.iter().map(|node| node_line("node", node)));
+        lines.extend(self.modulator_nodes.iter().map(|node| node_line("modulator-node", node)));
+        lines.join("\n") + "\n"
+    }
+
+    pub fn from_text(text:&str) -> Result<Session, String> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+
+        let version = lines.next()
+            .and_then(|header| header.strip_prefix(HEADER))
+            .and_then(|version| version.trim().parse::<u32>().ok())
+            .ok_or("not a waveshaper session")?;
+        if version > SESSION_VERSION {
+            return Err(format!("session is version {version}, this version of the app only reads up to {SESSION_VERSION}"));
+        }
+
+        let mut session = Session {
+            nodes:vec![],
+            interpolation:InterpolationMode::Linear,
+            band_limited:true,
+            frequency:None,
+            world_scale:1.0,
+            view_center:[0.0, 0.0],
+            max_voices:DEFAULT_MAX_VOICES,
+            steal_policy:StealPolicy::Oldest,
+            adsr:AdsrSettings::default(),
+            modulator_nodes:vec![],
+            modulator_interpolation:InterpolationMode::Linear,
+            modulator:ModulatorSettings::default(),
+            mod_matrix:ModMatrix::default(),
+        };
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "interpolation" => session.interpolation = parse_interpolation(value)?,
+                "modulator-interpolation" => session.modulator_interpolation = parse_interpolation(value)?,
+                "band-limited" => session.band_limited = parse(key, value)?,
+                "frequency" => session.frequency = Some(parse_number(key, value)?),
+                "world-scale" => session.world_scale = parse_number(key, value)?,
+                "max-voices" => session.max_voices = parse(key, value)?,
+                "voice-stealing" => {
+                    session.steal_policy = *StealPolicy::ALL.iter()
+                        .find(|policy| policy.name() == value)
+                        .ok_or_else(|| format!("unknown voice stealing '{value}'"))?;
+                },
+                "view-center" => {
+                    let (x, y) = value.split_once(' ').ok_or_else(|| format!("view center '{value}' should have 2 fields"))?;
+                    session.view_center = [parse_number(key, x)?, parse_number(key, y)?];
+                },
+                "envelope" => {
+                    let fields = value.split_whitespace().collect::<Vec<_>>();
+                    let [attack, decay, sustain, release, curve] = fields[..] else {
+                        return Err(format!("envelope '{value}' should have 5 fields"))
+                    };
+                    session.adsr = AdsrSettings {
+                        attack:parse_number(key, attack)?,
+                        decay:parse_number(key, decay)?,
+                        sustain:parse_number(key, sustain)?,
+                        release:parse_number(key, release)?,
+                        curve:*EnvelopeCurve::ALL.iter()
+                            .find(|probe| probe.name() == curve)
+                            .ok_or_else(|| format!("unknown envelope curve '{curve}'"))?,
+                    };
+                },
+                "modulator" => {
+                    let (mode, seconds) = value.split_once(' ').ok_or_else(|| format!("modulator '{value}' should have 2 fields"))?;
+                    session.modulator = ModulatorSettings {
+                        mode:*ModulatorMode::ALL.iter()
+                            .find(|probe| probe.name() == mode)
+                            .ok_or_else(|| format!("unknown modulator mode '{mode}'"))?,
+                        seconds:parse_number(key, seconds)?,
+                    };
+                },
+                "lfo" => {
+                    let fields = value.split_whitespace().collect::<Vec<_>>();
+                    let [index, shape, rate] = fields[..] else {
+                        return Err(format!("lfo '{value}' should have 3 fields"))
+                    };
+                    let lfo = slot(&mut session.mod_matrix.lfos, key, index)?;
+                    *lfo = LfoSettings {
+                        shape:*LfoShape::ALL.iter()
+                            .find(|probe| probe.name() == shape)
+                            .ok_or_else(|| format!("unknown lfo shape '{shape}'"))?,
+                        rate:parse_number(key, rate)?,
+                    };
+                },
+                "route" => {
+                    let fields = value.split_whitespace().collect::<Vec<_>>();
+                    let [index, source, target, depth] = fields[..] else {
+                        return Err(format!("route '{value}' should have 4 fields"))
+                    };
+                    let route = slot(&mut session.mod_matrix.routes, key, index)?;
+                    *route = ModRoute {
+                        source:*ModSource::ALL.iter()
+                            .find(|probe| probe.name() == source)
+                            .ok_or_else(|| format!("unknown modulation source '{source}'"))?,
+                        target:ModTarget::from_name(target).ok_or_else(|| format!("unknown modulation target '{target}'"))?,
+                        depth:parse_number(key, depth)?,
+                    };
+                },
+                "node" => session.nodes.push(parse_node(key, value)?),
+                "modulator-node" => session.modulator_nodes.push(parse_node(key, value)?),
+                _ => log::warn!("skipping unknown session setting '{key}'"),
+            }
+        }
+        // the wave keeps one node per position, the anchors drawn from the file would keep both
+        for (key, nodes) in [("node", &session.nodes), ("modulator-node", &session.modulator_nodes)] {
+            if let Some(wave_pos) = shared_position(nodes) {
+                return Err(format!("more than one {key} at position {wave_pos}"))
+            }
+        }
+        Ok(session)
+    }
+}
+
+fn parse_interpolation(value:&str) -> Result<InterpolationMode, String> {
+    InterpolationMode::ALL.iter()
+        .find(|mode| mode.name().replace(' ', "-") == value)
+        .copied()
+        .ok_or_else(|| format!("unknown interpolation mode '{value}'"))
+}
+
+fn node_line(key:&str, node:&WaveNode) -> String {
+    let [kind, param_a, param_b, _] = node.curve().gpu_params();
+    format!("{key} {} {} {kind} {param_a} {param_b}", node.wave_pos(), node.amplitude())
+}
+
+fn parse_node(key:&str, value:&str) -> Result<WaveNode, String> {
+    let fields = value.split_whitespace().map(|field| parse_number(key, field)).collect::<Result<Vec<_>, _>>()?;
+    let [wave_pos, amplitude, kind, param_a, param_b] = fields[..] else {
+        return Err(format!("{key} '{value}' should have 5 fields"))
+    };
+    // a node outside the cycle would break the order the wave is played in
+    if !(0.0..1.0).contains(&wave_pos) {
+        return Err(format!("{key} position {wave_pos} is outside the cycle"))
+    }
+    let curve = SegmentCurve::from_gpu_params([kind, param_a, param_b, 0.0]);
+    Ok(WaveNode::new(wave_pos, amplitude.clamp(-1.0, 1.0), curve))
+}
+
+// a position more than one of the nodes is at, if there is one
+fn shared_position(nodes:&[WaveNode]) -> Option<f32> {
+    let mut positions = nodes.iter().map(WaveNode::wave_pos).collect::<Vec<_>>();
+    positions.sort_by(f32::total_cmp);
+    positions.windows(2).find(|pair| pair[0] == pair[1]).map(|pair| pair[0])
+}
+
+// the entry a numbered line (counting from 1) is about
+fn slot<'a, T>(entries:&'a mut [T], key:&str, number:&str) -> Result<&'a mut T, String> {
+    let number:usize = parse(key, number)?;
+    let len = entries.len();
+    number.checked_sub(1)
+        .and_then(|index| entries.get_mut(index))
+        .ok_or_else(|| format!("{key} {number} is out of range, there are {len}"))
+}
+
+// NaN and infinity parse as floats, but would silence the engine (or worse) once they got into it
+fn parse_number(key:&str, value:&str) -> Result<f32, String> {
+    parse::<f32>(key, value).and_then(|number| match number.is_finite() {
+        true => Ok(number),
+        false => Err(format!("bad value '{value}' for {key}")),
+    })
+}
+
+fn parse<T:std::str::FromStr>(key:&str, value:&str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("bad value '{value}' for {key}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        let mut mod_matrix = ModMatrix::default();
+        mod_matrix.lfos[1] = LfoSettings { shape:LfoShape::Square, rate:2.5 };
+        mod_matrix.routes[3] = ModRoute { source:ModSource::Lfo(1), target:ModTarget::NodePosition(2), depth:-0.25 };
+        Session {
+            nodes:vec![
+                WaveNode::new(0.0, 0.5, SegmentCurve::Linear),
+                WaveNode::new(0.25, -1.0, SegmentCurve::Bezier(0.9, 0.1)),
+                WaveNode::new(0.75, 0.125, SegmentCurve::Exponential(4.0)),
+            ],
+            interpolation:InterpolationMode::CubicHermite,
+            band_limited:false,
+            frequency:Some(220.0),
+            world_scale:2.0,
+            view_center:[0.5, -0.25],
+            max_voices:4,
+            steal_policy:StealPolicy::Quietest,
+            adsr:AdsrSettings { attack:0.5, decay:0.25, sustain:0.75, release:1.5, curve:EnvelopeCurve::Linear },
+            modulator_nodes:vec![WaveNode::new(0.5, 1.0, SegmentCurve::SCurve(3.0))],
+            modulator_interpolation:InterpolationMode::Step,
+            modulator:ModulatorSettings { mode:ModulatorMode::Loop, seconds:4.0 },
+            mod_matrix,
+        }
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let saved = session();
+        let loaded = Session::from_text(&saved.to_text()).unwrap();
+        assert_eq!(loaded.nodes, saved.nodes);
+        assert_eq!(loaded.interpolation, saved.interpolation);
+        assert_eq!(loaded.band_limited, saved.band_limited);
+        assert_eq!(loaded.frequency, saved.frequency);
+        assert_eq!(loaded.world_scale, saved.world_scale);
+        assert_eq!(loaded.view_center, saved.view_center);
+        assert_eq!(loaded.max_voices, saved.max_voices);
+        assert_eq!(loaded.steal_policy, saved.steal_policy);
+        assert_eq!(loaded.adsr, saved.adsr);
+        assert_eq!(loaded.modulator_nodes, saved.modulator_nodes);
+        assert_eq!(loaded.modulator_interpolation, saved.modulator_interpolation);
+        assert_eq!(loaded.modulator, saved.modulator);
+        assert_eq!(loaded.mod_matrix, saved.mod_matrix);
+        assert_eq!(loaded.to_text(), saved.to_text());
+    }
+
+    #[test]
+    fn missing_settings_are_left_at_their_defaults() {
+        let loaded = Session::from_text("wasm-waveshaper session 1\nnode 0.5 0.25 0 0 0\n").unwrap();
+        assert_eq!(loaded.nodes, vec![WaveNode::new(0.5, 0.25, SegmentCurve::Linear)]);
+        assert_eq!(loaded.frequency, None);
+        assert_eq!(loaded.max_voices, DEFAULT_MAX_VOICES);
+        assert_eq!(loaded.mod_matrix, ModMatrix::default());
+    }
+
+    #[test]
+    fn rejects_nodes_sharing_a_position() {
+        let text = "wasm-waveshaper session 1\nnode 0.5 0.25 0 0 0\nnode 0.25 0 0 0 0\nnode 0.5 -0.25 0 0 0\n";
+        assert!(Session::from_text(text).is_err());
+        let text = "wasm-waveshaper session 1\nnode 0.5 0.25 0 0 0\nmodulator-node 0.5 0 0 0 0\nmodulator-node 0.5 1 0 0 0\n";
+        assert!(Session::from_text(text).is_err());
+        // the two layers are separate shapes, the same position on each is fine
+        let text = "wasm-waveshaper session 1\nnode 0.5 0.25 0 0 0\nmodulator-node 0.5 0 0 0 0\n";
+        assert!(Session::from_text(text).is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        for line in ["frequency NaN", "world-scale inf", "node 1 0 0 0 0", "node -0.1 0 0 0 0", "node 0.5 0", "route 9 lfo-1 pitch 1",
+                "interpolation wobbly", "lfo 0 sine 1"] {
+            let text = format!("wasm-waveshaper session 1\n{line}\n");
+            assert!(Session::from_text(&text).is_err(), "'{line}' should be rejected");
+        }
+    }
+
+    #[test]
+    fn rejects_newer_versions_and_other_files() {
+        assert!(Session::from_text(&format!("wasm-waveshaper session {}\n", SESSION_VERSION + 1)).is_err());
+        assert!(Session::from_text("RIFF").is_err());
+        assert!(Session::from_text("").is_err());
+    }
+}
@@ -3,8 +3,11 @@
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) color:vec3<f32>, // TODO: are we overwriting the vert buffer (position part that is at loc 0) ??
-    @location(2) slope_intercept:vec2<f32>,
+    @location(2) seg_start:vec2<f32>,
     @location(3) world_pos:vec3<f32>,
+    @location(4) seg_end:vec2<f32>,
+    @location(5) curve:vec4<f32>,
+    @location(6) outer_nbrs:vec4<f32>,
 };
 
 // !!! WGSL INTERPRETS MATRICES AS SETS OF COLUMN VECTORS !!!
@@ -14,10 +17,136 @@ struct GraphicsInput {
     cursor_pixel_pos:vec4<f32>,
     world_to_clip_transfm:mat4x4<f32>,
     canvas_dimensions:vec4<u32>,
+    // [x divisions, y steps, visible, unused], see rendering::Grid
+    grid:vec4<f32>,
+    // [interpolation kind, unused, unused, unused], see audio::InterpolationMode::gpu_kind()
+    interpolation:vec4<u32>,
 }
 
 @group(0) @binding(0) var<uniform> graphics_input: GraphicsInput;
 
+// maps the linear progress through a segment to the curved progress, the kinds mirror audio::SegmentCurve
+// THIS MUST MATCH SegmentCurve::shape() IN segment_curve.rs so that the card looks the way the wave sounds
+fn shape_progress(progress:f32, curve:vec4<f32>) -> f32 {
+    let t = clamp(progress, 0.0, 1.0);
+    switch u32(curve[0]) {
+        // exponential
+        case 1u: {
+            return exp_shape(t, curve[1]);
+        }
+        // logarithmic
+        case 2u: {
+            return 1.0 - exp_shape(1.0 - t, curve[1]);
+        }
+        // s-curve
+        case 3u: {
+            if t < 0.5 {
+                return 0.5 * pow(2.0 * t, curve[1]);
+            }
+            return 1.0 - 0.5 * pow(2.0 * (1.0 - t), curve[1]);
+        }
+        // bezier
+        case 4u: {
+            let inv = 1.0 - t;
+            return 3.0 * inv * inv * t * curve[1] + 3.0 * inv * t * t * curve[2] + t * t * t;
+        }
+        // linear
+        default: {
+            return t;
+        }
+    }
+}
+
+// value of the segment between p1 and p2 at progress t, each point is (x, amplitude) with the x positions increasing
+// THIS MUST MATCH InterpolationMode::interpolate() IN interpolation.rs, the kind numbers come from gpu_kind()
+fn interpolate(kind:u32, p0:vec2<f32>, p1:vec2<f32>, p2:vec2<f32>, p3:vec2<f32>, t:f32) -> f32 {
+    let seg_len = p2.x - p1.x;
+    switch kind {
+        // step
+        case 1u: {
+            return p1.y;
+        }
+        // cosine
+        case 2u: {
+            let mu = (1.0 - cos(t * 3.14159265)) / 2.0;
+            return mix(p1.y, p2.y, mu);
+        }
+        // cubic hermite
+        case 3u: {
+            let m1 = (secant(p0, p1) + secant(p1, p2)) / 2.0;
+            let m2 = (secant(p1, p2) + secant(p2, p3)) / 2.0;
+            return hermite(p1.y, p2.y, m1 * seg_len, m2 * seg_len, t);
+        }
+        // catmull-rom
+        case 4u: {
+            return hermite(p1.y, p2.y, secant(p0, p2) * seg_len, secant(p1, p3) * seg_len, t);
+        }
+        // monotone cubic
+        case 5u: {
+            let d0 = secant(p0, p1);
+            let d1 = secant(p1, p2);
+            let d2 = secant(p2, p3);
+            var m1 = 0.0;
+            var m2 = 0.0;
+            if d0 * d1 > 0.0 {
+                m1 = (d0 + d1) / 2.0;
+            }
+            if d1 * d2 > 0.0 {
+                m2 = (d1 + d2) / 2.0;
+            }
+            if d1 == 0.0 {
+                m1 = 0.0;
+                m2 = 0.0;
+            } else {
+                let alpha = m1 / d1;
+                let beta = m2 / d1;
+                let mag = alpha * alpha + beta * beta;
+                if mag > 9.0 {
+                    let tau = 3.0 / sqrt(mag);
+                    m1 = tau * alpha * d1;
+                    m2 = tau * beta * d1;
+                }
+            }
+            return hermite(p1.y, p2.y, m1 * seg_len, m2 * seg_len, t);
+        }
+        // linear
+        default: {
+            return mix(p1.y, p2.y, t);
+        }
+    }
+}
+
+// slope between two points, flat if they share a position
+fn secant(start:vec2<f32>, end:vec2<f32>) -> f32 {
+    let run = end.x - start.x;
+    if run <= 0.0 {
+        return 0.0;
+    }
+    return (end.y - start.y) / run;
+}
+
+// cubic hermite spline between two values, tangents are expected to already be scaled by the segment length
+fn hermite(start:f32, end:f32, start_tangent:f32, end_tangent:f32, t:f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    return (2.0 * t3 - 3.0 * t2 + 1.0) * start
+        + (t3 - 2.0 * t2 + t) * start_tangent
+        + (-2.0 * t3 + 3.0 * t2) * end
+        + (t3 - t2) * end_tangent;
+}
+
+// position measured in grid cells, the grid lines split -1 to 1 on each axis and sit on whole numbers of cells
+fn grid_cells(world_pos:vec2<f32>) -> vec2<f32> {
+    return (world_pos + 1.0) * graphics_input.grid.xy / 2.0;
+}
+
+fn exp_shape(t:f32, steepness:f32) -> f32 {
+    if abs(steepness) < 0.001 {
+        return t;
+    }
+    return (exp(steepness * t) - 1.0) / (exp(steepness) - 1.0);
+}
+
 // Vertex Shader
 // @location(0) is the position of the vert in clip space, written to the vertex buffer in rendering.rs
 // @location(1) is the color that we assigned to this vert and wrote to the vertex buffer
@@ -30,6 +159,8 @@ fn vert_main(
     @location(2) instance_pos:vec2<f32>,
     @location(3) right_nbr_pos:vec3<f32>,
     @location(4) instance_scale:f32,
+    @location(5) curve:vec4<f32>,
+    @location(6) outer_nbr_pos:vec4<f32>,
 ) -> VertexOutput {
     var return_data:VertexOutput;
     // write some data to the vertex's position attribute, THIS VALUE WILL BE CHANGED INBETWEEN THE VERT AND FRAG SHADERS
@@ -40,48 +171,61 @@ fn vert_main(
         return_data.position = vec4<f32>(world_position, 1.0); // dont transform to clip space, the background coords are actually already in clip space
         // color of background plane corresponds with the UV coords of the plane
         return_data.color = vec3<f32>(color[0], color[1], 0.0);
+        // world position of the corner so the grid can be drawn behind the wave, the transform only scales and moves
+        let transfm = graphics_input.world_to_clip_transfm;
+        return_data.world_pos = vec3(
+            (world_position[0] - transfm[3][0]) / transfm[0][0],
+            (world_position[1] - transfm[3][1]) / transfm[1][1],
+            0.0
+        );
 
         return return_data; // doesn't need anything else if it is the background 
     }
     
-    // nbr_wrap is a value that enables the wave shape visualization to extend past the right border of the screen
-    // it will onlt apply to circle instances that are on the furthest right point of the screen
-    // TODO: here "20.0" is a magic number representing the unit width of the screen in world coords
-    //  this will need to be brought in here so that the wrapping the wav viz works regardless of aspect ratio
-    var card_scale:vec3<f32>;
-    // max_ext can be aaplied to x or y scale to make the card extend past the edges of the view
-    var max_ext:f32 = (1.0/graphics_input.world_to_clip_transfm[1][1]) * 4.0;
-    if right_nbr_pos[0] < instance_pos[0] {
-        card_scale = vec3(max_ext, max_ext, 0.0);
-    }
-    else {
-        card_scale = vec3(right_nbr_pos[0] - instance_pos[0], max_ext, 1.0);
+    // the segment runs from this node to its right neighbor
+    // rightmost segment takes into account that the position of the right neighbor of the
+    // rightmost node's right neighbor should be treated as though it is in the next cycle in order to appear cyclical
+    var seg_end = right_nbr_pos.xy;
+    if (right_nbr_pos[0] <= instance_pos[0]) {
+        // one cycle of the wave spans -1 to 1 in world x
+        seg_end[0] = right_nbr_pos[0] + 2.0;
     }
 
+    // the card spans the segment across and the whole view top to bottom, so the area between the wave and the zero
+    // line gets filled in wherever the view has been moved to
+    let transfm = graphics_input.world_to_clip_transfm;
+    let view_height = 2.0 / transfm[1][1];
+    let view_center_y = -transfm[3][1] / transfm[1][1];
+    let card_scale = vec3(seg_end[0] - instance_pos[0], view_height, 1.0);
+
     // pass the world position
-    return_data.world_pos = world_position * vec3(instance_scale, instance_scale, 1.0) * card_scale + vec3(instance_pos, 0.0);
+    return_data.world_pos = world_position * vec3(instance_scale, 1.0, 1.0) * card_scale + vec3(instance_pos[0], view_center_y, 0.0);
 
     // the vert shader for the circle instances
-    return_data.position = graphics_input.world_to_clip_transfm * vec4(return_data.world_pos, 1.0);
+    return_data.position = transfm * vec4(return_data.world_pos, 1.0);
     
     // todo: highlight this circle if the cursor is hovering over it
     return_data.color = vec3(0.0, world_position[1] * instance_scale + instance_pos[1], 0.0);
 
-    var slope:f32;
-    // rightmost slope takes into account that the position of the right neighbor of the
-    // rightmost node's right neighbor should be treated as though it is off screen to the right in order to appear cyclical
-    if (right_nbr_pos[0] < instance_pos[0]) {
-        // world_to_clip_transfm[1][1] represents the current world scale
-        slope = (right_nbr_pos[1] - instance_pos[1]) / ( (right_nbr_pos[0] + 2.0 * (1.0/graphics_input.world_to_clip_transfm[1][1])) - instance_pos[0] );
-    } else {
-        // normal slope calculation
-        slope = (right_nbr_pos[1] - instance_pos[1]) / (right_nbr_pos[0] - instance_pos[0]);
+    return_data.seg_start = instance_pos;
+    return_data.seg_end = seg_end;
+    return_data.curve = curve;
+
+    // the neighbors either side of the segment, moved a whole cycle where they wrap around so the x positions always
+    // increase across the four points, the same way Wave::segment_value() unwraps them
+    var left_nbr = outer_nbr_pos.xy;
+    if (left_nbr[0] >= instance_pos[0]) {
+        left_nbr[0] = left_nbr[0] - 2.0;
     }
-    //const slope = 2.0, // TODO: placeholder slope
-    return_data.slope_intercept = vec2(
-        slope,
-        (instance_pos[1] + (instance_pos[0] * -1.0 * slope)) // y axis intercept
-    );
+    var second_right_nbr = outer_nbr_pos.zw;
+    // with fewer than three anchors it can be the same anchor as the segment's end, or its start, a cycle or two back
+    if (second_right_nbr[0] <= seg_end[0]) {
+        second_right_nbr[0] = second_right_nbr[0] + 2.0;
+    }
+    if (second_right_nbr[0] <= seg_end[0]) {
+        second_right_nbr[0] = second_right_nbr[0] + 2.0;
+    }
+    return_data.outer_nbrs = vec4(left_nbr, second_right_nbr);
     
     return return_data;
 }
@@ -99,6 +243,11 @@ fn frag_main(
     // We assigned a clip space postion to the @builtin position attribute before, but now it has been transformed
     // into the framebuffer coordinate position of this fragment. This happened INBETWEEN vert and frag stages
     // whereas the color will be a direct interpolation of the value we assigned in the vert shader
+
+    // grid lines are about a pixel wide, the line width comes from derivatives so it has to be worked out before any
+    // early returns below
+    var cells = grid_cells(vert_data.world_pos.xy);
+    var on_grid_line = any(abs(cells - round(cells)) < fwidth(cells)) && graphics_input.grid[2] > 0.5;
     
     var diff_vec = vert_data.position - graphics_input.cursor_pixel_pos;
     var cull = diff_vec[0] > 50.0 || diff_vec[1] > 50.0;
@@ -110,16 +259,33 @@ fn frag_main(
         }
     }
 
+    // height of the wave at this fragment's x position, following the curve of the segment
+    var seg_width = vert_data.seg_end[0] - vert_data.seg_start[0];
+    var progress = 0.0;
+    if seg_width > 0.0 {
+        progress = (vert_data.world_pos[0] - vert_data.seg_start[0]) / seg_width;
+    }
+    var wave_height = interpolate(
+        graphics_input.interpolation[0],
+        vert_data.outer_nbrs.xy,
+        vert_data.seg_start,
+        vert_data.seg_end,
+        vert_data.outer_nbrs.zw,
+        shape_progress(progress, vert_data.curve),
+    );
+
     // if this fragment falls between the waveshaping line and the zero baseline, shade it in
-    if ( (vert_data.world_pos[1] < (vert_data.slope_intercept[0] * vert_data.world_pos[0]) + vert_data.slope_intercept[1] && 
-         vert_data.world_pos[1] > 0.0) ||
-         (vert_data.world_pos[1] > (vert_data.slope_intercept[0] * vert_data.world_pos[0]) + vert_data.slope_intercept[1] &&
-         vert_data.world_pos[1] < 0.0 )
+    if ( (vert_data.world_pos[1] < wave_height && vert_data.world_pos[1] > 0.0) ||
+         (vert_data.world_pos[1] > wave_height && vert_data.world_pos[1] < 0.0)
     ) {
         //this is where the shader for the wave visualization is defined
         return vec4<f32>(1.0, 1.0, 1.0, 1.0); // show the shape of the wave in white
     }
     
+    if on_grid_line {
+        return vec4<f32>(0.3, 0.3, 0.3, 1.0);
+    }
+
     // transparent, because not in area under the waveshape, shows the webpage background not background plane :(
     // there must need to be multiple draw calls if I want one plane rendered on top of the other?
     return vec4<f32>(.0, 0.0, 0.0, 0.0);
@@ -0,0 +1,242 @@
+// Shapes packed into the page URL so a link to the page is a link to the sound
+// The node list, interpolation mode and frequency are quantized into a few bytes per node and base64url encoded into the
+// fragment (the part after #), which browsers never send to a server. Whoever opens the link decodes the same quantized
+// nodes, so everyone with the link hears exactly the same wave.
+//
+// layout, all little endian:
+// version u8, interpolation index u8, frequency f32,
+// the frequency and nodes are left out altogether when there's no wave, otherwise per node: wave pos u16 (in 1/65536ths), amplitude i16 (in 1/32767ths), curve kind u8, curve params i16 (in 1/1000ths),
+// one param for exponential/logarithmic/s-curve, two for bezier, none for linear
+
+use crate::audio::{InterpolationMode, SegmentCurve, WaveNode};
+
+const LINK_VERSION:u8 = 1;
+const WAVE_POS_SCALE:f32 = 65536.0;
+const AMPLITUDE_SCALE:f32 = 32767.0;
+const CURVE_PARAM_SCALE:f32 = 1000.0;
+const BASE64URL_ALPHABET:&[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// what a link carries, the rest of the session (view, playback settings) stays as it is on the receiving end
+pub struct SharedShape {
+    pub nodes:Vec<WaveNode>,
+    pub interpolation:InterpolationMode,
+    // None when there's no wave
+    pub frequency:Option<f32>,
+}
+
+pub fn encode(shape:&SharedShape) -> String {
+    let mode_index = InterpolationMode::ALL.iter().position(|mode| *mode == shape.interpolation).unwrap_or(0);
+    let mut bytes = vec![LINK_VERSION, mode_index as u8];
+    let Some(frequency) = shape.frequency else {
+        return base64url_encode(&bytes)
+    };
+    bytes.extend(frequency.to_le_bytes());
+    for node in &shape.nodes {
+        let wave_pos = (node.wave_pos().rem_euclid(1.0) * WAVE_POS_SCALE).round().min(WAVE_POS_SCALE - 1.0) as u16;
+        let amplitude = (node.amplitude().clamp(-1.0, 1.0) * AMPLITUDE_SCALE).round() as i16;
+        bytes.extend(wave_pos.to_le_bytes());
+        bytes.extend(amplitude.to_le_bytes());
+
+        let [kind, param_a, param_b, _] = node.curve().gpu_params();
+        bytes.push(kind as u8);
+        for param in &[param_a, param_b][..num_curve_params(kind as u8)] {
+            bytes.extend(((param * CURVE_PARAM_SCALE).round() as i16).to_le_bytes());
+        }
+    }
+    base64url_encode(&bytes)
+}
+
+pub fn decode(text:&str) -> Result<SharedShape, String> {
+    let bytes = base64url_decode(text)?;
+    let mut reader = ByteReader(&bytes);
+
+    let version = reader.u8()?;
+    if version != LINK_VERSION {
+        return Err(format!("link is version {version}, this version of the app reads version {LINK_VERSION}"));
+    }
+    let interpolation = *InterpolationMode::ALL.get(reader.u8()? as usize).ok_or("link has an unknown interpolation mode")?;
+    if reader.0.is_empty() {
+        return Ok(SharedShape { nodes:vec![], interpolation, frequency:None })
+    }
+    let frequency = f32::from_le_bytes(reader.take()?);
+    // any four bytes are some float, a link can be edited by hand to carry NaN or infinity
+    if !frequency.is_finite() || frequency <= 0.0 {
+        return Err(format!("link has a frequency of {frequency}"));
+    }
+
+    let mut nodes = vec![];
+    while !reader.0.is_empty() {
+        let wave_pos = u16::from_le_bytes(reader.take()?) as f32 / WAVE_POS_SCALE;
+        let amplitude = i16::from_le_bytes(reader.take()?) as f32 / AMPLITUDE_SCALE;
+        let kind = reader.u8()?;
+        let mut params = [0.0f32; 2];
+        for param in &mut params[..num_curve_params(kind)] {
+            *param = i16::from_le_bytes(reader.take()?) as f32 / CURVE_PARAM_SCALE;
+        }
+        let curve = SegmentCurve::from_gpu_params([kind as f32, params[0], params[1], 0.0]);
+        // nodes closer together than the quantizing come out at the same position, the wave only keeps one node per
+        // position so only the first is kept here as well
+        if nodes.iter().any(|node:&WaveNode| node.wave_pos() == wave_pos) {
+            continue
+        }
+        nodes.push(WaveNode::new(wave_pos, amplitude, curve));
+    }
+    Ok(SharedShape { nodes, interpolation, frequency:Some(frequency) })
+}
+
+// how many parameters follow each kind of curve, see SegmentCurve::gpu_params()
+fn num_curve_params(kind:u8) -> usize {
+    match kind {
+        1..=3 => 1,
+        4 => 2,
+        _ => 0,
+    }
+}
+
+struct ByteReader<'a>(&'a [u8]);
+
+impl ByteReader<'_> {
+    fn take<const N:usize>(&mut self) -> Result<[u8; N], String> {
+        if self.0.len() < N {
+            return Err("link is cut short".to_string())
+        }
+        let (taken, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(taken.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take::<1>()?[0])
+    }
+}
+
+// base64 with - and _ in place of + and /, and no padding, so it can go in a URL as is
+fn base64url_encode(bytes:&[u8]) -> String {
+    let mut text = String::with_capacity((bytes.len() * 4).div_ceil(3));
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| group | (*byte as u32) << (16 - 8 * index));
+        for index in 0..chunk.len() + 1 {
+            text.push(BASE64URL_ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char);
+        }
+    }
+    text
+}
+
+fn base64url_decode(text:&str) -> Result<Vec<u8>, String> {
+    let digits = text.trim_end_matches('=').bytes()
+        .map(|char| BASE64URL_ALPHABET.iter().position(|digit| *digit == char).map(|digit| digit as u32))
+        .collect::<Option<Vec<u32>>>()
+        .ok_or("link has characters that aren't base64url")?;
+    if digits.len() % 4 == 1 {
+        return Err("link is cut short".to_string())
+    }
+
+    let mut bytes = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, digit)| group | digit << (18 - 6 * index));
+        for index in 0..chunk.len() - 1 {
+            bytes.push((group >> (16 - 8 * index)) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+// replaces the fragment of the page URL without adding a browser history entry for every edit
+#[cfg(target_arch = "wasm32")]
+pub fn write_to_page_url(fragment:&str) {
+    let Some(history) = web_sys::window().and_then(|win| win.history().ok()) else {
+        return
+    };
+    if let Err(err) = history.replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(&format!("#{fragment}"))) {
+        log::warn!("couldn't update the page url: {:?}", err);
+    }
+}
+
+// there's no page natively, so nowhere to put a link
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_to_page_url(_fragment:&str) {}
+
+// the fragment the page was opened with, without the #
+#[cfg(target_arch = "wasm32")]
+pub fn read_from_page_url() -> Option<String> {
+    let hash = web_sys::window()?.location().hash().ok()?;
+    Some(hash.strip_prefix('#').unwrap_or(&hash).to_string()).filter(|fragment| !fragment.is_empty())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_from_page_url() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(nodes:Vec<WaveNode>) -> SharedShape {
+        SharedShape { nodes, interpolation:InterpolationMode::CatmullRom, frequency:Some(220.0) }
+    }
+
+    #[test]
+    fn round_trips_through_a_link() {
+        let nodes = vec![
+            WaveNode::new(0.0, 0.5, SegmentCurve::Linear),
+            WaveNode::new(0.25, -1.0, SegmentCurve::Exponential(4.0)),
+            WaveNode::new(0.5, 1.0, SegmentCurve::SCurve(3.0)),
+            WaveNode::new(0.75, 0.25, SegmentCurve::Bezier(0.9, 0.1)),
+        ];
+        let decoded = decode(&encode(&shape(nodes.clone()))).unwrap();
+        assert_eq!(decoded.interpolation, InterpolationMode::CatmullRom);
+        assert_eq!(decoded.frequency, Some(220.0));
+        assert_eq!(decoded.nodes.len(), nodes.len());
+        for (decoded, node) in decoded.nodes.iter().zip(nodes.iter()) {
+            assert!((decoded.wave_pos() - node.wave_pos()).abs() <= 0.5 / WAVE_POS_SCALE);
+            assert!((decoded.amplitude() - node.amplitude()).abs() <= 0.5 / AMPLITUDE_SCALE);
+            assert_eq!(decoded.curve(), node.curve());
+        }
+    }
+
+    #[test]
+    fn a_link_without_a_wave_has_no_frequency() {
+        let link = encode(&SharedShape { nodes:vec![], interpolation:InterpolationMode::Step, frequency:None });
+        let decoded = decode(&link).unwrap();
+        assert_eq!(decoded.frequency, None);
+        assert_eq!(decoded.interpolation, InterpolationMode::Step);
+        assert!(decoded.nodes.is_empty());
+    }
+
+    #[test]
+    fn nodes_closer_than_a_step_come_back_as_one() {
+        let nodes = vec![
+            WaveNode::new(0.5, 0.25, SegmentCurve::Linear),
+            WaveNode::new(0.5 + 0.1 / WAVE_POS_SCALE, -0.25, SegmentCurve::Linear),
+            WaveNode::new(0.5 + 2.0 / WAVE_POS_SCALE, 0.5, SegmentCurve::Linear),
+        ];
+        let decoded = decode(&encode(&shape(nodes))).unwrap();
+        let positions = decoded.nodes.iter().map(WaveNode::wave_pos).collect::<Vec<_>>();
+        assert_eq!(positions, vec![0.5, 0.5 + 2.0 / WAVE_POS_SCALE]);
+        assert!((decoded.nodes[0].amplitude() - 0.25).abs() <= 0.5 / AMPLITUDE_SCALE);
+    }
+
+    #[test]
+    fn rejects_malformed_links() {
+        let link = encode(&shape(vec![WaveNode::new(0.25, 0.5, SegmentCurve::Bezier(0.9, 0.1))]));
+        // every cut through the node or the frequency leaves something missing, only cuts right before the frequency or
+        // right after it leave a link without nodes
+        let clean_cuts = [base64url_encode(&[0; 2]).len(), base64url_encode(&[0; 6]).len()];
+        for len in (1..link.len()).filter(|len| !clean_cuts.contains(len)) {
+            assert!(decode(&link[..len]).is_err(), "'{}' should be rejected", &link[..len]);
+        }
+        assert!(decode("not a link!").is_err());
+
+        let with_header = |version:u8, mode:u8, frequency:f32| {
+            let mut bytes = vec![version, mode];
+            bytes.extend(frequency.to_le_bytes());
+            base64url_encode(&bytes)
+        };
+        assert!(decode(&with_header(LINK_VERSION, 0, 110.0)).is_ok());
+        assert!(decode(&with_header(LINK_VERSION + 1, 0, 110.0)).is_err());
+        assert!(decode(&with_header(LINK_VERSION, InterpolationMode::ALL.len() as u8, 110.0)).is_err());
+        assert!(decode(&with_header(LINK_VERSION, 0, f32::NAN)).is_err());
+        assert!(decode(&with_header(LINK_VERSION, 0, 0.0)).is_err());
+    }
+}